@@ -1,22 +1,29 @@
 use derive_more::From;
 use futures::future::TryFutureExt as _;
 use futures::stream::{self, Stream};
-use log::debug;
+use log::{debug, error};
+use reqwest::multipart::{Form, Part};
 use reqwest::{Client, Request};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::fmt;
+use std::fs::File;
 use std::future::Future;
+use std::io;
 use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use telegram_types::bot::inline_mode::{AnswerInlineQuery, InlineQueryId, InlineQueryResult};
 use telegram_types::bot::methods::{
-    ApiError, ChatTarget, DeleteMessage, EditMessageText, GetMe, GetUpdates, Method, SendMessage,
-    TelegramResult,
+    ApiError, ChatTarget, DeleteMessage, DeleteWebhook, EditMessageText, GetChatMember, GetMe,
+    GetUpdates, Method, SendDocument, SendMessage, SetWebhook, TelegramResult,
 };
-use telegram_types::bot::types::{ChatId, Message, MessageId, ParseMode, Update, UpdateId};
+use telegram_types::bot::types::{
+    ChatId, ChatMember, Message, MessageId, ParseMode, Update, UpdateId, UserId,
+};
+use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::time::timeout;
 
 const TELEGRAM_TIMEOUT_SECS: u16 = 30;
@@ -43,23 +50,63 @@ impl Bot {
     }
 
     pub fn get_updates(&self) -> impl Stream<Item = Result<Option<Update>, Error>> + '_ {
-        #[derive(Default)]
         struct Data {
             update_id: Option<UpdateId>,
             buffer: VecDeque<Update>,
+            offset_file: PathBuf,
+            /// Offset which has been written to the offset file.
+            saved_update_id: Option<UpdateId>,
+            /// Whether any webhook left from a previous run has been removed,
+            /// as getUpdates doesn't work while a webhook is set.
+            webhook_deleted: bool,
         }
 
         fn bump_update_id(data: &mut Data, update_id: UpdateId) {
             data.update_id = Some(UpdateId(update_id.0 + 1));
         }
 
-        stream::unfold(Default::default(), move |mut data: Data| {
+        /// Persist the offset if it has changed since the last save.
+        ///
+        /// This is only called once the buffer is drained, i.e. all the updates before the offset
+        /// have been handed off, and the write is done on the blocking thread pool.
+        async fn persist_update_id(data: &mut Data) {
+            let update_id = match data.update_id {
+                Some(update_id) if data.saved_update_id != Some(update_id) => update_id,
+                _ => return,
+            };
+            let path = data.offset_file.clone();
+            let result =
+                tokio::task::spawn_blocking(move || save_update_offset(&path, update_id)).await;
+            if let Err(e) = result {
+                error!("failed to save update offset: {:?}", e);
+            }
+            data.saved_update_id = Some(update_id);
+        }
+
+        // Resume from the offset persisted in the previous run, if any.
+        let offset_file = PathBuf::from(format!("update_offset_{}.json", self.username));
+        let update_id = load_update_offset(&offset_file);
+        let data = Data {
+            update_id,
+            buffer: VecDeque::new(),
+            offset_file,
+            saved_update_id: update_id,
+            webhook_deleted: false,
+        };
+        stream::unfold(data, move |mut data: Data| {
             async move {
                 let result = loop {
                     if let Some(update) = data.buffer.pop_front() {
                         debug!("{}: {:?}", self.username, update);
                         break Ok(Some(update));
                     }
+                    if !data.webhook_deleted {
+                        if let Err(err) = self.build_request(&DeleteWebhook).execute().await {
+                            break Err(err);
+                        }
+                        data.webhook_deleted = true;
+                    }
+                    persist_update_id(&mut data).await;
                     let mut get_updates = GetUpdates::new();
                     if let Some(update_id) = data.update_id {
                         get_updates.offset(update_id);
@@ -95,6 +142,34 @@ impl Bot {
         })
     }
 
+    /// Set the webhook of the bot to the given URL with the given secret token,
+    /// then yield updates from the receiver.
+    pub fn run_webhook(
+        &self,
+        url: String,
+        secret_token: String,
+        updates: UnboundedReceiver<Update>,
+    ) -> impl Stream<Item = Result<Option<Update>, Error>> + '_ {
+        stream::unfold(
+            (false, url, secret_token, updates),
+            move |(webhook_set, url, secret_token, mut updates)| async move {
+                if !webhook_set {
+                    let set_webhook = SetWebhookWithSecret {
+                        inner: SetWebhook::new(url.as_str()),
+                        secret_token: &secret_token,
+                    };
+                    if let Err(err) = self.build_request(&set_webhook).execute().await {
+                        return Some((Err(err), (false, url, secret_token, updates)));
+                    }
+                    debug!("{}: webhook set", self.username);
+                }
+                let update = updates.recv().await.expect("webhook route never goes away");
+                debug!("{}: {:?}", self.username, update);
+                Some((Ok(Some(update)), (true, url, secret_token, updates)))
+            },
+        )
+    }
+
     pub fn confirm_update(&self, update_id: UpdateId) -> impl Future<Output = Result<(), Error>> {
         let mut get_updates = GetUpdates::new();
         get_updates.offset(UpdateId(update_id.0 + 1));
@@ -112,6 +187,20 @@ impl Bot {
         self.build_request(&send_message)
     }
 
+    /// Send the given text in reply to the given message.
+    pub fn reply_message<'a>(
+        &self,
+        chat_id: ChatId,
+        reply_to: MessageId,
+        text: impl Into<Cow<'a, str>>,
+    ) -> BotRequest<Message> {
+        let mut send_message = SendMessage::new(ChatTarget::id(chat_id.0), text)
+            .parse_mode(ParseMode::HTML)
+            .reply(reply_to);
+        send_message.disable_web_page_preview = Some(true);
+        self.build_request(&send_message)
+    }
+
     pub fn edit_message<'a>(
         &self,
         chat_id: ChatId,
@@ -124,6 +213,32 @@ impl Bot {
         self.build_request(&edit_message)
     }
 
+    /// Send the given content as a document with the given file name,
+    /// in reply to the given message.
+    pub fn send_document(
+        &self,
+        chat_id: ChatId,
+        reply_to: MessageId,
+        file_name: impl Into<String>,
+        content: impl Into<Vec<u8>>,
+    ) -> BotRequest<Message> {
+        let document = Part::bytes(content.into()).file_name(file_name.into());
+        let form = Form::new()
+            .text("chat_id", chat_id.0.to_string())
+            .text("reply_to_message_id", reply_to.0.to_string())
+            .part("document", document);
+        let request = self
+            .client
+            .post(SendDocument::url(self.token))
+            .multipart(form)
+            .build();
+        BotRequest {
+            client: self.client.clone(),
+            request,
+            phantom: PhantomData,
+        }
+    }
+
     pub fn delete_message(&self, chat_id: ChatId, message_id: MessageId) -> BotRequest<bool> {
         let delete_message = DeleteMessage {
             chat_id: ChatTarget::id(chat_id.0),
@@ -132,17 +247,31 @@ impl Bot {
         self.build_request(&delete_message)
     }
 
+    pub fn get_chat_member(&self, chat_id: ChatId, user_id: UserId) -> BotRequest<ChatMember> {
+        let get_chat_member = GetChatMember {
+            chat_id: ChatTarget::id(chat_id.0),
+            user_id,
+        };
+        self.build_request(&get_chat_member)
+    }
+
     pub fn answer_inline_query(
         &self,
         inline_query_id: InlineQueryId,
         results: &[InlineQueryResult<'_>],
+        options: InlineQueryOptions,
     ) -> BotRequest<bool> {
+        let InlineQueryOptions {
+            cache_time,
+            is_personal,
+            next_offset,
+        } = options;
         let answer = AnswerInlineQuery {
             inline_query_id,
             results: results.into(),
-            cache_time: None,
-            is_personal: None,
-            next_offset: None,
+            cache_time: cache_time.map(|t| t.as_secs().try_into().unwrap_or(i32::MAX)),
+            is_personal,
+            next_offset: next_offset.map(Cow::from),
             switch_pm_text: None,
             switch_pm_parameter: None,
         };
@@ -162,6 +291,17 @@ impl Bot {
     }
 }
 
+/// Options of answering inline query, where `None` means the default of Telegram.
+#[derive(Debug, Default)]
+pub struct InlineQueryOptions {
+    /// How long the results may be cached by Telegram.
+    pub cache_time: Option<Duration>,
+    /// Whether the results are only cached for the user who sent the query.
+    pub is_personal: Option<bool>,
+    /// Offset to be sent in the next query to get more results.
+    pub next_offset: Option<String>,
+}
+
 pub struct BotRequest<T> {
     client: Client,
     request: Result<Request, reqwest::Error>,
@@ -187,6 +327,50 @@ where
     }
 }
 
+/// `setWebhook` with the `secret_token` parameter, which isn't supported by `SetWebhook`.
+#[derive(Serialize)]
+struct SetWebhookWithSecret<'a> {
+    #[serde(flatten)]
+    inner: SetWebhook<'a>,
+    /// Sent back in the `X-Telegram-Bot-Api-Secret-Token` header of each webhook request.
+    secret_token: &'a str,
+}
+
+impl Method for SetWebhookWithSecret<'_> {
+    const NAME: &'static str = SetWebhook::NAME;
+    type Item = <SetWebhook<'static> as Method>::Item;
+}
+
+fn load_update_offset(path: &Path) -> Option<UpdateId> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            // It's fine that the file doesn't exist.
+            if e.kind() != io::ErrorKind::NotFound {
+                error!("failed to read update offset: {:?}", e);
+            }
+            return None;
+        }
+    };
+    match serde_json::from_reader(file) {
+        Ok(update_id) => Some(update_id),
+        Err(e) => {
+            error!("failed to parse update offset: {:?}", e);
+            None
+        }
+    }
+}
+
+fn save_update_offset(path: &Path, update_id: UpdateId) {
+    match File::create(path) {
+        Ok(file) => match serde_json::to_writer(file, &update_id) {
+            Ok(()) => {}
+            Err(e) => error!("failed to serialize update offset: {:?}", e),
+        },
+        Err(e) => error!("failed to create update offset: {:?}", e),
+    }
+}
+
 // All the inner fields are used as part of `Debug` output, so they are not dead code.
 // But rustc explicitly excludes derive from dead code analysis, thus we need to allow dead code
 // below to avoid triggering the warning.
@@ -197,6 +381,46 @@ pub enum Error {
     Parse(ParseError),
 }
 
+impl Error {
+    /// Whether the request is known to have failed without taking effect,
+    /// so that retrying it may succeed without duplicating e.g. a sent message.
+    ///
+    /// Timeouts and other errors after the request is sent are not included,
+    /// because Telegram may have handled the request anyway.
+    pub fn is_safe_to_retry(&self) -> bool {
+        match self {
+            Error::Request(e) => e.is_connect(),
+            Error::Api(e) => e.error_code >= 500,
+            Error::Parse(_) => false,
+        }
+    }
+
+    /// How long to wait before retrying, if Telegram asks so for flood control.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::Api(ApiError {
+                error_code: 429,
+                parameters: Some(parameters),
+                ..
+            }) => {
+                let secs = parameters.retry_after?;
+                Some(Duration::from_secs(u64::try_from(secs).ok()?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the error is because an edit doesn't change the message.
+    pub fn is_message_not_modified(&self) -> bool {
+        match self {
+            Error::Api(e) => {
+                e.error_code == 400 && e.description.contains("message is not modified")
+            }
+            _ => false,
+        }
+    }
+}
+
 pub struct ParseError {
     pub data: Vec<u8>,
     pub error: serde_json::Error,
@@ -228,3 +452,163 @@ fn may_recover_from_error(error: &Error) -> Option<UpdateId> {
     let id = item.get("update_id")?.as_i64()?;
     Some(UpdateId(id))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::CONTENT_TYPE;
+    use std::env;
+    use std::fs;
+    use telegram_types::bot::types::ResponseParameters;
+
+    #[test]
+    fn test_send_document() {
+        let bot = Bot {
+            client: Client::new(),
+            token: "TOKEN",
+            username: "bot",
+        };
+        let request = bot
+            .send_document(ChatId(42), MessageId(7), "output.txt", "hello")
+            .request
+            .unwrap();
+        assert_eq!(request.method(), reqwest::Method::POST);
+        assert_eq!(
+            request.url().as_str(),
+            "https://api.telegram.org/botTOKEN/sendDocument"
+        );
+        let content_type = request.headers()[CONTENT_TYPE].to_str().unwrap();
+        assert!(content_type.starts_with("multipart/form-data; boundary="));
+    }
+
+    #[test]
+    fn test_answer_inline_query_options() {
+        let bot = Bot {
+            client: Client::new(),
+            token: "TOKEN",
+            username: "bot",
+        };
+        let body = |options| {
+            let request = bot
+                .answer_inline_query(InlineQueryId("1".to_string()), &[], options)
+                .request
+                .unwrap();
+            let body = request.body().unwrap().as_bytes().unwrap();
+            serde_json::from_slice::<JsonValue>(body).unwrap()
+        };
+        // Telegram defaults are used if nothing is specified.
+        assert_eq!(
+            body(InlineQueryOptions::default()),
+            serde_json::json!({ "inline_query_id": "1", "results": [] })
+        );
+        let options = InlineQueryOptions {
+            cache_time: Some(Duration::from_secs(300)),
+            is_personal: Some(true),
+            next_offset: Some("2".to_string()),
+        };
+        assert_eq!(
+            body(options),
+            serde_json::json!({
+                "inline_query_id": "1",
+                "results": [],
+                "cache_time": 300,
+                "is_personal": true,
+                "next_offset": "2",
+            })
+        );
+    }
+
+    #[test]
+    fn test_update_offset() {
+        let path = env::temp_dir().join(format!("update_offset_{}.json", std::process::id()));
+        // Missing file starts fresh.
+        assert_eq!(load_update_offset(&path), None);
+        save_update_offset(&path, UpdateId(42));
+        assert_eq!(load_update_offset(&path), Some(UpdateId(42)));
+        // Corrupt file starts fresh as well.
+        fs::write(&path, "not a number").unwrap();
+        assert_eq!(load_update_offset(&path), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_is_safe_to_retry() {
+        let api_error = |error_code| api_error(error_code, "");
+        assert!(api_error(500).is_safe_to_retry());
+        assert!(api_error(502).is_safe_to_retry());
+        assert!(!api_error(400).is_safe_to_retry());
+        assert!(!api_error(403).is_safe_to_retry());
+        // Flood control needs waiting rather than an immediate retry.
+        assert!(!api_error(429).is_safe_to_retry());
+        let parse_error = Error::Parse(ParseError {
+            data: b"<html>".to_vec(),
+            error: serde_json::from_slice::<JsonValue>(b"<html>").unwrap_err(),
+        });
+        assert!(!parse_error.is_safe_to_retry());
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let client = Client::builder()
+                .timeout(Duration::from_millis(100))
+                .build()
+                .unwrap();
+            // Nothing can be sent if the connection fails.
+            let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/", closed.local_addr().unwrap());
+            drop(closed);
+            let err = client.get(&url).send().await.unwrap_err();
+            assert!(Error::from(err).is_safe_to_retry());
+            // The request may have been handled if the response times out.
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            let err = client.get(&url).send().await.unwrap_err();
+            assert!(!Error::from(err).is_safe_to_retry());
+        });
+    }
+
+    #[test]
+    fn test_is_message_not_modified() {
+        let not_modified = api_error(
+            400,
+            "Bad Request: message is not modified: specified new message content \
+             and reply markup are exactly the same as a current content and reply markup \
+             of the message",
+        );
+        assert!(not_modified.is_message_not_modified());
+        let not_found = api_error(400, "Bad Request: message to edit not found");
+        assert!(!not_found.is_message_not_modified());
+        assert!(!api_error(500, "message is not modified").is_message_not_modified());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let rate_limited = |retry_after| {
+            Error::Api(ApiError {
+                error_code: 429,
+                description: "Too Many Requests: retry after 7".to_string(),
+                parameters: Some(ResponseParameters {
+                    migrate_to_chat_id: None,
+                    retry_after,
+                }),
+            })
+        };
+        assert_eq!(
+            rate_limited(Some(7)).retry_after(),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(rate_limited(None).retry_after(), None);
+        assert_eq!(rate_limited(Some(-1)).retry_after(), None);
+        assert_eq!(api_error(429, "").retry_after(), None);
+    }
+
+    fn api_error(error_code: i32, description: &str) -> Error {
+        Error::Api(ApiError {
+            error_code,
+            description: description.to_string(),
+            parameters: None,
+        })
+    }
+}
@@ -1,12 +1,16 @@
 use crate::bot::{Bot, Error};
 use crate::shutdown::Shutdown;
 use crate::task_tracker::TaskSpawner;
+use crate::update_log;
+use crate::upgrade;
 use crate::utils;
+use crate::webhook;
 use futures::channel::oneshot::{channel, Receiver};
 use futures::future;
 use futures::pin_mut;
 use futures::stream::{Stream, StreamExt as _};
 use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
 use reqwest::Client;
 use std::env::{self, VarError};
 use std::future::Future;
@@ -15,6 +19,52 @@ use std::time::Duration;
 use telegram_types::bot::types::{Update, UpdateContent, UpdateId};
 use tokio::time::sleep;
 
+/// Default number of retries with exponential backoff before capping the delay.
+const DEFAULT_FAST_RETRIES: u32 = 8;
+/// Upper bound of `BOT_FAST_RETRIES`, which caps the retry delay at 2^16 seconds.
+const MAX_FAST_RETRIES: u32 = 16;
+
+static FAST_RETRIES: Lazy<u32> = Lazy::new(|| {
+    let retries = env::var("BOT_FAST_RETRIES").map_or(DEFAULT_FAST_RETRIES, |s| {
+        s.parse().expect("BOT_FAST_RETRIES must be a valid number")
+    });
+    retries.min(MAX_FAST_RETRIES)
+});
+
+/// Names of all the bots which can be listed in `ENABLED_BOTS`.
+const BOT_NAMES: &[&str] = &["eval", "cratesio", "rustdoc"];
+
+/// Names of bots enabled via `ENABLED_BOTS`, or `None` if all of them are.
+static ENABLED_BOTS: Lazy<Option<Vec<String>>> = Lazy::new(|| {
+    let names = env::var("ENABLED_BOTS").ok()?;
+    Some(parse_enabled_bots(&names))
+});
+
+/// Parse the comma-separated list of bot names, skipping empty entries.
+fn parse_enabled_bots(names: &str) -> Vec<String> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            assert!(
+                BOT_NAMES.contains(&name),
+                "unknown bot in ENABLED_BOTS: {name}, expected one of {BOT_NAMES:?}"
+            );
+            name.to_string()
+        })
+        .collect()
+}
+
+/// Whether the bot with the given name is enabled.
+pub fn is_enabled(name: &str) -> bool {
+    is_enabled_in(ENABLED_BOTS.as_deref(), name)
+}
+
+fn is_enabled_in(enabled_bots: Option<&[String]>, name: &str) -> bool {
+    enabled_bots.is_none_or(|names| names.iter().any(|n| n == name))
+}
+
 pub struct BotRunner<'a> {
     pub client: &'a Client,
     pub spawner: &'a Arc<TaskSpawner>,
@@ -37,6 +87,11 @@ impl<'a> BotRunner<'a> {
         HandleResult: Future<Output = ()> + Send + 'static,
     {
         let (sender, receiver) = channel();
+        if !is_enabled(name) {
+            info!("{} wouldn't start because it's not in ENABLED_BOTS", name);
+            sender.send(Ok(None)).unwrap();
+            return receiver;
+        }
         let token = match env::var(token_env) {
             Ok(token) => Box::leak(token.into_boxed_str()),
             Err(VarError::NotPresent) => {
@@ -63,9 +118,16 @@ impl<'a> BotRunner<'a> {
             };
             sender.send(Ok(Some(bot.clone()))).unwrap();
             let stop_signal = shutdown.register();
+            let updates = match &*webhook::CONFIG {
+                Some(config) => {
+                    let (url, secret_token, receiver) = webhook::register(config, token);
+                    bot.run_webhook(url, secret_token, receiver).left_stream()
+                }
+                None => bot.get_updates().right_stream(),
+            };
             let bot_runner = run_bot(
                 &bot,
-                bot.get_updates(),
+                updates,
                 Arc::new(create_impl(bot.clone())),
                 handle_update,
                 spawner,
@@ -92,7 +154,7 @@ async fn run_bot<Impl, Handler, HandleResult>(
     HandleResult: Future<Output = ()> + Send + 'static,
 {
     pin_mut!(stream);
-    let mut retried = 0;
+    let mut retried: u32 = 0;
     let mut delay = None;
     loop {
         if let Some(delay) = delay.take() {
@@ -103,11 +165,15 @@ async fn run_bot<Impl, Handler, HandleResult>(
             Some(Ok(maybe_update)) => {
                 retried = 0;
                 if let Some(Update { update_id, content }) = maybe_update {
-                    debug!("{}> handling", update_id.0);
-                    let content = content.unwrap_or_default();
-                    if !may_handle_common_command(update_id, &content, bot, &spawner, &shutdown) {
-                        spawner.spawn((handle_update)(bot_impl.clone(), update_id, content));
-                    }
+                    update_log::sync_scope(update_id, || {
+                        debug!("handling");
+                        let content = content.unwrap_or_default();
+                        if !may_handle_common_command(update_id, &content, bot, &spawner, &shutdown)
+                        {
+                            let handler = (handle_update)(bot_impl.clone(), update_id, content);
+                            spawner.spawn(update_log::scope(update_id, handler));
+                        }
+                    });
                 }
             }
             Some(Err(e)) => {
@@ -116,13 +182,17 @@ async fn run_bot<Impl, Handler, HandleResult>(
                     "{}: telegram error ({} retries): {:?}",
                     bot.username, retried, e,
                 );
-                if retried >= 13 {
-                    error!("{}: retried too many times!", bot.username);
-                    break;
+                if let Some(retry_after) = e.retry_after() {
+                    // Flood control tells us exactly how long to wait.
+                    delay = Some(sleep(retry_after));
                 } else {
-                    let delay_duration = Duration::from_secs(1 << retried);
+                    if retried == *FAST_RETRIES {
+                        error!("{}: retried too many times, slowing down", bot.username);
+                    }
+                    // Keep retrying at the maximum delay once fast retries are exhausted.
+                    let delay_duration = Duration::from_secs(1 << retried.min(*FAST_RETRIES));
                     delay = Some(sleep(delay_duration));
-                    retried += 1;
+                    retried = retried.saturating_add(1);
                 }
             }
         }
@@ -134,7 +204,7 @@ fn may_handle_common_command(
     content: &UpdateContent,
     bot: &Bot,
     spawner: &Arc<TaskSpawner>,
-    shutdown: &Shutdown,
+    shutdown: &Arc<Shutdown>,
 ) -> bool {
     let message = match &content {
         UpdateContent::Message(message) => message,
@@ -150,39 +220,87 @@ fn may_handle_common_command(
     let chat_id = message.chat.id;
     let send_reply = |text: &str| {
         let future = bot.send_message(chat_id, text).execute();
-        spawner.spawn(async move {
+        spawner.spawn(update_log::scope(update_id, async move {
             match future.await {
-                Ok(msg) => debug!(
-                    "{}> sent about message as {}",
-                    update_id.0, msg.message_id.0
-                ),
-                Err(err) => warn!("{}> error: {:?}", update_id.0, err),
+                Ok(msg) => debug!("sent about message as {}", msg.message_id.0),
+                Err(err) => warn!("error: {:?}", err),
             }
-        });
+        }));
     };
+    let is_admin = message
+        .from
+        .as_ref()
+        .is_some_and(|from| from.id == *crate::ADMIN_ID);
     match command.trim() {
         "/about" => {
             send_reply(&crate::ABOUT_MESSAGE);
         }
-        "/shutdown" => {
-            let is_admin = message
-                .from
-                .as_ref()
-                .map_or(false, |from| from.id == *crate::ADMIN_ID);
-            if !is_admin {
-                return false;
+        "/status" if is_admin => {
+            let status = format!(
+                "version: {}\nuptime: {}\ntasks: {}",
+                env!("VERSION"),
+                utils::format_duration(crate::START_TIME.elapsed()),
+                spawner.active_count(),
+            );
+            send_reply(&status);
+        }
+        command @ ("/shutdown" | "/restart") if is_admin => {
+            let restart = command == "/restart";
+            if restart {
+                send_reply("restarting...");
+            } else {
+                send_reply("start shutting down...");
+                shutdown.shutdown();
             }
-            send_reply("start shutting down...");
-            shutdown.shutdown();
             let bot = bot.clone();
-            spawner.spawn(async move {
-                let result = bot.confirm_update(update_id).await;
-                if let Err(e) = result {
-                    error!("failed to confirm: {:?}", e);
+            let shutdown = shutdown.clone();
+            spawner.spawn(update_log::scope(update_id, async move {
+                // Updates from webhook are confirmed once they are received.
+                if webhook::CONFIG.is_none() {
+                    let result = bot.confirm_update(update_id).await;
+                    if let Err(e) = result {
+                        error!("failed to confirm: {:?}", e);
+                    }
+                }
+                if restart {
+                    // Go through the upgrade watcher, so that the supervising process
+                    // sees the same signal as a normal upgrade.
+                    if let Err(e) = upgrade::trigger() {
+                        error!("failed to trigger upgrade: {:?}", e);
+                        shutdown.shutdown();
+                    }
                 }
-            });
+            }));
         }
         _ => return false,
     }
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_enabled() {
+        assert!(is_enabled_in(None, "eval"));
+        let names = ["eval".to_string(), "rustdoc".to_string()];
+        assert!(is_enabled_in(Some(&names), "eval"));
+        assert!(is_enabled_in(Some(&names), "rustdoc"));
+        assert!(!is_enabled_in(Some(&names), "cratesio"));
+        assert!(!is_enabled_in(Some(&[]), "eval"));
+    }
+
+    #[test]
+    fn test_parse_enabled_bots() {
+        assert_eq!(parse_enabled_bots("eval, rustdoc"), ["eval", "rustdoc"]);
+        assert_eq!(parse_enabled_bots("eval,,cratesio,"), ["eval", "cratesio"]);
+        assert!(parse_enabled_bots("").is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown bot in ENABLED_BOTS: evl")]
+    fn test_parse_enabled_bots_unknown() {
+        parse_enabled_bots("evl,rustdoc");
+    }
+}
@@ -1,12 +1,17 @@
-use crate::bot::Bot;
-use crate::utils::encode_with_code;
+use crate::bot::{Bot, InlineQueryOptions};
+use crate::latest_query::LatestQueries;
+use crate::utils::{encode_with_code, format_count};
+use futures::stream::{self, StreamExt as _};
 use htmlescape::encode_minimal;
 use itertools::Itertools;
 use log::{debug, info, warn};
-use reqwest::{Client, IntoUrl};
+use parking_lot::Mutex;
+use reqwest::{Client, IntoUrl, StatusCode};
 use serde::Deserialize;
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use telegram_types::bot::inline_mode::{
     InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputTextMessageContent,
     ResultId,
@@ -15,17 +20,52 @@ use telegram_types::bot::types::{
     InlineKeyboardButton, InlineKeyboardButtonPressed, InlineKeyboardMarkup, ParseMode,
     UpdateContent, UpdateId,
 };
+use tokio::time::timeout;
 use url::Url;
 
+/// How long results of a query are cached.
+const CACHE_TTL: Duration = Duration::from_secs(90);
+
+/// How long Telegram may cache the results of a search.
+const SEARCH_CACHE_TIME: Duration = Duration::from_secs(5 * 60);
+/// How long Telegram may cache the summary shown for the empty query.
+const SUMMARY_CACHE_TIME: Duration = Duration::from_secs(30 * 60);
+
+/// Timeout for querying crates.io.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Maximum number of concurrent requests for versions of crates in one page.
+const MAX_VERSIONS_REQUESTS: usize = 3;
+
+/// Number of results requested from crates.io for each page.
+const PER_PAGE: u32 = 50;
+
+#[derive(Clone, Debug)]
+struct Results {
+    items: Vec<InlineQueryResult<'static>>,
+    /// Page to be requested when the user scrolls to the end of the results.
+    next_page: Option<u32>,
+}
+
+type CachedResults = (Instant, Results);
+
 pub struct CratesioBot {
     client: Client,
     bot: Bot,
+    /// Cached results keyed by normalized query and page.
+    cache: Mutex<HashMap<(String, u32), CachedResults>>,
+    latest_queries: LatestQueries,
 }
 
 impl CratesioBot {
     pub fn new(client: Client, bot: Bot) -> Self {
         info!("CratesioBot authorized as @{}", bot.username);
-        CratesioBot { client, bot }
+        CratesioBot {
+            client,
+            bot,
+            cache: Mutex::new(HashMap::new()),
+            latest_queries: Default::default(),
+        }
     }
 
     pub async fn handle_update(self: Arc<Self>, _: UpdateId, content: UpdateContent) {
@@ -33,30 +73,46 @@ impl CratesioBot {
             UpdateContent::InlineQuery(query) => query,
             _ => return,
         };
-        let result = if query.query.is_empty() {
-            self.generate_results("https://crates.io/api/v1/summary", |resp: Summary| {
-                resp.most_recently_downloaded
-            })
-            .await
+        let latest_query = self.latest_queries.observe(query.from.id, &query.id);
+        let key = (query.query.trim().to_lowercase(), parse_page(&query.offset));
+        let cache_time = if key.0.is_empty() {
+            SUMMARY_CACHE_TIME
         } else {
-            let mut url = Url::parse("https://crates.io/api/v1/crates").unwrap();
-            url.query_pairs_mut()
-                .append_pair("q", &query.query)
-                .append_pair("sort", "relevance")
-                .append_pair("per_page", "50");
-            self.generate_results(url, |resp: Crates| resp.crates).await
+            SEARCH_CACHE_TIME
         };
-        let result = match result {
-            Ok(result) => result,
-            Err(e) => {
-                warn!("failed to get results: {:?}", e);
-                return;
+        let result = match self.get_cached(&key) {
+            Some(result) => {
+                debug!("using cached results for {:?}", key);
+                result
             }
+            None => match timeout(REQUEST_TIMEOUT, self.query_results(&key.0, key.1)).await {
+                Ok(Ok(result)) => {
+                    self.put_cached(key, result.clone());
+                    result
+                }
+                Ok(Err(e)) => {
+                    warn!("failed to get results: {:?}", e);
+                    return;
+                }
+                Err(_elapsed) => {
+                    warn!("timed out getting results for {:?}", key);
+                    return;
+                }
+            },
         };
+        if !latest_query.should_answer() {
+            debug!("skipping stale query {:?}", query.query);
+            return;
+        }
         debug!("replying: {:?}", result);
+        let options = InlineQueryOptions {
+            cache_time: Some(cache_time),
+            next_offset: result.next_page.map(|page| page.to_string()),
+            ..Default::default()
+        };
         let result = self
             .bot
-            .answer_inline_query(query.id, &result)
+            .answer_inline_query(query.id, &result.items, options)
             .execute()
             .await;
         if let Err(e) = result {
@@ -64,25 +120,241 @@ impl CratesioBot {
         }
     }
 
-    async fn generate_results<T>(
-        &self,
-        url: impl IntoUrl,
-        get_crates: impl FnOnce(T) -> Vec<Crate>,
-    ) -> Result<Vec<InlineQueryResult<'static>>, reqwest::Error>
+    fn get_cached(&self, key: &(String, u32)) -> Option<Results> {
+        let cache = self.cache.lock();
+        let (time, result) = cache.get(key)?;
+        (time.elapsed() < CACHE_TTL).then(|| result.clone())
+    }
+
+    fn put_cached(&self, key: (String, u32), result: Results) {
+        let mut cache = self.cache.lock();
+        cache.retain(|_, (time, _)| time.elapsed() < CACHE_TTL);
+        cache.insert(key, (Instant::now(), result));
+    }
+
+    async fn query_results(&self, query: &str, page: u32) -> Result<Results, reqwest::Error> {
+        let (category, query) = parse_category(query);
+        if query.is_empty() && category.is_none() {
+            // There is only one page of the summary.
+            if page > 1 {
+                let items = vec![];
+                let next_page = None;
+                return Ok(Results { items, next_page });
+            }
+            let resp: Summary = self.fetch("https://crates.io/api/v1/summary").await?;
+            let mut crates = resp.most_recently_downloaded;
+            self.fill_versions(&mut crates).await;
+            Ok(generate_results(crates, None))
+        } else {
+            let mut url = Url::parse("https://crates.io/api/v1/crates").unwrap();
+            {
+                let mut pairs = url.query_pairs_mut();
+                if query.is_empty() {
+                    // Browse the most popular crates in the category.
+                    pairs.append_pair("sort", "recent-downloads");
+                } else {
+                    pairs
+                        .append_pair("q", query)
+                        .append_pair("sort", "relevance");
+                }
+                if let Some(category) = category {
+                    pairs.append_pair("category", category);
+                }
+                pairs
+                    .append_pair("page", &page.to_string())
+                    .append_pair("per_page", &PER_PAGE.to_string());
+            }
+            let resp: Crates = self.fetch(url).await?;
+            let mut crates = resp.crates;
+            // The exact match may not be in the category, so only pin it in plain searches.
+            if !query.is_empty() && category.is_none() {
+                if page == 1 {
+                    if !pin_exact_match(&mut crates, query) {
+                        if let Some(krate) = self.get_crate(query).await {
+                            crates.insert(0, krate);
+                        }
+                    }
+                } else {
+                    // The exact match has been shown at the top of the first page.
+                    crates.retain(|c| !is_same_name(&c.name, query));
+                }
+            }
+            self.fill_versions(&mut crates).await;
+            Ok(generate_results(crates, next_page(page, resp.meta.total)))
+        }
+    }
+
+    /// Look up the crate with exactly the given name.
+    async fn get_crate(&self, name: &str) -> Option<Crate> {
+        if !is_valid_crate_name(name) {
+            return None;
+        }
+        let mut url = Url::parse("https://crates.io/api/v1/crates").unwrap();
+        url.path_segments_mut().unwrap().push(name);
+        match self.fetch::<CrateResponse>(url).await {
+            Ok(resp) => Some(Crate {
+                versions: resp.versions,
+                ..resp.krate
+            }),
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => None,
+            Err(e) => {
+                warn!("failed to look up crate {:?}: {:?}", name, e);
+                None
+            }
+        }
+    }
+
+    /// Fetch versions of crates which have no stable version available,
+    /// so that a version which is not yanked can be shown for them.
+    async fn fill_versions(&self, crates: &mut [Crate]) {
+        let crates = crates
+            .iter_mut()
+            .filter(|c| c.max_stable_version.is_none() && c.versions.is_empty())
+            .collect::<Vec<_>>();
+        stream::iter(crates)
+            .for_each_concurrent(MAX_VERSIONS_REQUESTS, |c| self.fill_crate_versions(c))
+            .await;
+    }
+
+    async fn fill_crate_versions(&self, krate: &mut Crate) {
+        let mut url = Url::parse("https://crates.io/api/v1/crates").unwrap();
+        url.path_segments_mut()
+            .unwrap()
+            .extend([&krate.name, "versions"]);
+        url.query_pairs_mut().append_pair("sort", "semver");
+        match self.fetch::<Versions>(url).await {
+            Ok(resp) => krate.versions = resp.versions,
+            Err(e) => warn!("failed to get versions of {:?}: {:?}", krate.name, e),
+        }
+    }
+
+    async fn fetch<T>(&self, url: impl IntoUrl) -> Result<T, reqwest::Error>
     where
         for<'de> T: Deserialize<'de>,
     {
         let resp = self.client.get(url).send().await?;
         let resp = resp.error_for_status()?;
-        let resp = resp.json().await?;
-        let crates = get_crates(resp)
-            .into_iter()
-            .map(|c| c.into_inline_query_result())
-            .collect();
-        Ok(crates)
+        resp.json().await
+    }
+}
+
+fn generate_results(crates: Vec<Crate>, next_page: Option<u32>) -> Results {
+    let items = crates
+        .into_iter()
+        .map(|c| c.into_inline_query_result())
+        .collect();
+    Results { items, next_page }
+}
+
+/// Whether the crate name can only be `name` on crates.io,
+/// which ignores case and doesn't distinguish `-` and `_`.
+fn is_same_name(name: &str, query: &str) -> bool {
+    let normalize = |c: char| match c {
+        '-' => '_',
+        c => c.to_ascii_lowercase(),
+    };
+    name.len() == query.len() && name.chars().map(normalize).eq(query.chars().map(normalize))
+}
+
+/// Whether the string can be a crate name, so that it's worth looking up.
+fn is_valid_crate_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Move the crate with the name of the query to the top of the list.
+///
+/// Returns whether there is such a crate.
+fn pin_exact_match(crates: &mut [Crate], query: &str) -> bool {
+    match crates.iter().position(|c| is_same_name(&c.name, query)) {
+        Some(index) => {
+            crates[..=index].rotate_right(1);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Split the optional `cat:<slug>` prefix of the query, which filters crates by category.
+fn parse_category(query: &str) -> (Option<&str>, &str) {
+    let Some(rest) = query.strip_prefix("cat:") else {
+        return (None, query);
+    };
+    let (category, query) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let category = Some(category).filter(|c| !c.is_empty());
+    (category, query.trim())
+}
+
+/// Parse the offset of inline query as page number, where empty offset means the first page.
+fn parse_page(offset: &str) -> u32 {
+    offset.parse().ok().filter(|&page| page > 0).unwrap_or(1)
+}
+
+/// Get the next page if there are more than `total` results after the given page.
+fn next_page(page: u32, total: u64) -> Option<u32> {
+    (u64::from(page) * u64::from(PER_PAGE) < total).then_some(page + 1)
+}
+
+/// Format download counts like `⬇ 12.3M (recent 890k)`.
+fn format_downloads(downloads: u64, recent_downloads: Option<u64>) -> String {
+    match recent_downloads {
+        Some(recent) => format!(
+            "⬇ {} (recent {})",
+            format_count(downloads),
+            format_count(recent)
+        ),
+        None => format!("⬇ {}", format_count(downloads)),
     }
 }
 
+/// Select the version of a crate to show, and a note if it's not the max version.
+///
+/// Yanked versions and pre-releases are skipped when possible.
+/// `versions` should be from newest to oldest, and it's only used when
+/// there is no stable version available.
+fn select_version<'a>(
+    max_version: &'a str,
+    max_stable_version: Option<&'a str>,
+    versions: &'a [CrateVersion],
+) -> (&'a str, Option<String>) {
+    let version = match max_stable_version {
+        Some(version) => version,
+        None => match versions.iter().find(|v| !v.yanked) {
+            Some(version) => &version.num,
+            None => return (max_version, None),
+        },
+    };
+    if version == max_version {
+        return (version, None);
+    }
+    let is_max_yanked = max_stable_version.is_none()
+        || !is_pre_release(max_version)
+        || versions.iter().any(|v| v.num == max_version && v.yanked);
+    let note = if is_max_yanked {
+        format!("{max_version} yanked")
+    } else {
+        format!("latest {max_version} is a pre-release")
+    };
+    (version, Some(note))
+}
+
+/// Whether the version is a pre-release, e.g. `2.0.0-rc.1`.
+fn is_pre_release(version: &str) -> bool {
+    let version = version
+        .split_once('+')
+        .map_or(version, |(version, _)| version);
+    version.contains('-')
+}
+
+/// Get the date part of an RFC 3339 timestamp like `2023-10-04T12:34:56.789+00:00`.
+fn format_date(timestamp: &str) -> &str {
+    timestamp
+        .split_once('T')
+        .map_or(timestamp, |(date, _)| date)
+}
+
 #[derive(Debug, Deserialize)]
 struct Summary {
     most_recently_downloaded: Vec<Crate>,
@@ -91,6 +363,31 @@ struct Summary {
 #[derive(Debug, Deserialize)]
 struct Crates {
     crates: Vec<Crate>,
+    meta: Meta,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: Crate,
+    #[serde(default)]
+    versions: Vec<CrateVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Versions {
+    versions: Vec<CrateVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateVersion {
+    num: String,
+    yanked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+    total: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -99,8 +396,18 @@ struct Crate {
     name: String,
     description: Option<String>,
     max_version: String,
+    /// Newest stable version which is not yanked.
+    max_stable_version: Option<String>,
     documentation: Option<String>,
     repository: Option<String>,
+    downloads: u64,
+    recent_downloads: Option<u64>,
+    license: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+    /// Versions from newest to oldest, only fetched when there is no stable version.
+    #[serde(skip)]
+    versions: Vec<CrateVersion>,
 }
 
 impl Crate {
@@ -110,26 +417,65 @@ impl Crate {
             name,
             description,
             max_version,
+            max_stable_version,
             documentation,
             repository,
+            downloads,
+            recent_downloads,
+            license,
+            created_at,
+            updated_at,
+            versions,
         } = self;
 
-        let description: Option<Cow<'_, str>> =
-            description.map(|d| d.split_whitespace().join(" ").into());
-        let title = format!("{name} {max_version}");
+        let description = description.map(|d| d.split_whitespace().join(" "));
+        let (version, note) =
+            select_version(&max_version, max_stable_version.as_deref(), &versions);
+        let version = match &note {
+            Some(note) => format!("{version}, {note}"),
+            None => version.to_string(),
+        };
+        let title = format!("{name} {version}");
         let mut message = format!(
             "<b>{}</b> ({})",
             encode_minimal(&name),
-            encode_minimal(&max_version)
+            encode_minimal(&version)
         );
         if let Some(description) = &description {
             message.push('\n');
             encode_with_code(&mut message, description);
         }
+        if let Some(license) = &license {
+            message.push_str(&format!("\nLicense: {}", encode_minimal(license)));
+        }
+        let created = created_at.as_deref().map(format_date);
+        let updated = updated_at.as_deref().map(format_date);
+        match (created, updated) {
+            (Some(created), Some(updated)) => {
+                message.push_str(&format!("\nCreated {created}, updated {updated}"));
+            }
+            (Some(created), None) => message.push_str(&format!("\nCreated {created}")),
+            (None, Some(updated)) => message.push_str(&format!("\nUpdated {updated}")),
+            (None, None) => {}
+        }
+        let mut stats = format_downloads(downloads, recent_downloads);
+        message.push('\n');
+        message.push_str(&stats);
+        if let Some(updated) = updated {
+            stats.push_str(&format!(", updated {updated}"));
+        }
+        if let Some(license) = &license {
+            stats.push_str(&format!(" · {license}"));
+        }
+        let description: Cow<'_, str> = match description {
+            Some(description) => format!("{stats}\n{description}").into(),
+            None => stats.into(),
+        };
 
         // The name can only use alphanumeric characters or `-` and `_`, so no escape is needed.
         // See https://doc.rust-lang.org/cargo/reference/manifest.html#the-name-field
         let crate_url = format!("https://crates.io/crates/{name}");
+        let versions_url = format!("https://crates.io/crates/{name}/versions");
         let doc_url = documentation.unwrap_or_else(|| format!("https://docs.rs/crate/{name}"));
         let mut buttons = vec![
             InlineKeyboardButton {
@@ -140,6 +486,10 @@ impl Crate {
                 text: "doc".to_string(),
                 pressed: InlineKeyboardButtonPressed::Url(doc_url),
             },
+            InlineKeyboardButton {
+                text: "versions".to_string(),
+                pressed: InlineKeyboardButtonPressed::Url(versions_url),
+            },
         ];
         if let Some(repo) = repository {
             buttons.push(InlineKeyboardButton {
@@ -161,10 +511,268 @@ impl Crate {
             }),
             url: None,
             hide_url: None,
-            description,
+            description: Some(description),
             thumb_url: None,
             thumb_width: None,
             thumb_height: None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_category() {
+        assert_eq!(parse_category("tokio"), (None, "tokio"));
+        assert_eq!(
+            parse_category("cat:web-programming tokio"),
+            (Some("web-programming"), "tokio")
+        );
+        assert_eq!(
+            parse_category("cat:web-programming::http-server  axum web"),
+            (Some("web-programming::http-server"), "axum web")
+        );
+        assert_eq!(parse_category("cat:gui"), (Some("gui"), ""));
+        assert_eq!(parse_category("cat: tokio"), (None, "tokio"));
+        assert_eq!(
+            parse_category("serde cat:encoding"),
+            (None, "serde cat:encoding")
+        );
+    }
+
+    #[test]
+    fn test_pagination() {
+        assert_eq!(parse_page(""), 1);
+        assert_eq!(parse_page("0"), 1);
+        assert_eq!(parse_page("x"), 1);
+        assert_eq!(parse_page("3"), 3);
+        assert_eq!(next_page(1, 0), None);
+        assert_eq!(next_page(1, 50), None);
+        assert_eq!(next_page(1, 51), Some(2));
+        assert_eq!(next_page(2, 120), Some(3));
+        assert_eq!(next_page(3, 120), None);
+    }
+
+    #[test]
+    fn test_format_downloads() {
+        assert_eq!(format_downloads(999, None), "⬇ 999");
+        assert_eq!(
+            format_downloads(12_345_678, Some(890_123)),
+            "⬇ 12.3M (recent 890k)"
+        );
+    }
+
+    fn krate(name: &str) -> Crate {
+        Crate {
+            id: name.to_string(),
+            name: name.to_string(),
+            description: None,
+            max_version: "1.0.0".to_string(),
+            max_stable_version: Some("1.0.0".to_string()),
+            documentation: None,
+            repository: None,
+            downloads: 0,
+            recent_downloads: None,
+            license: None,
+            created_at: None,
+            updated_at: None,
+            versions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_select_version() {
+        let versions = |list: &[(&str, bool)]| {
+            list.iter()
+                .map(|&(num, yanked)| CrateVersion {
+                    num: num.to_string(),
+                    yanked,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select_version("1.0.0", Some("1.0.0"), &[]), ("1.0.0", None));
+        assert_eq!(
+            select_version("2.0.0-rc.1", Some("1.5.0"), &[]),
+            (
+                "1.5.0",
+                Some("latest 2.0.0-rc.1 is a pre-release".to_string())
+            )
+        );
+        assert_eq!(
+            select_version("1.6.0", Some("1.5.0"), &[]),
+            ("1.5.0", Some("1.6.0 yanked".to_string()))
+        );
+        // Only pre-releases.
+        let list = versions(&[("0.1.0-beta.2", false), ("0.1.0-beta.1", false)]);
+        assert_eq!(
+            select_version("0.1.0-beta.2", None, &list),
+            ("0.1.0-beta.2", None)
+        );
+        let list = versions(&[("0.1.0-beta.2", true), ("0.1.0-beta.1", false)]);
+        assert_eq!(
+            select_version("0.1.0-beta.2", None, &list),
+            ("0.1.0-beta.1", Some("0.1.0-beta.2 yanked".to_string()))
+        );
+        // Everything is yanked, or versions are not available.
+        let list = versions(&[("0.2.0", true), ("0.1.0", true)]);
+        assert_eq!(select_version("0.2.0", None, &list), ("0.2.0", None));
+        assert_eq!(select_version("0.2.0", None, &[]), ("0.2.0", None));
+    }
+
+    #[test]
+    fn test_is_pre_release() {
+        assert!(!is_pre_release("1.0.0"));
+        assert!(is_pre_release("2.0.0-rc.1"));
+        assert!(!is_pre_release("1.0.0+build-5"));
+        assert!(is_pre_release("1.0.0-alpha+build"));
+    }
+
+    #[test]
+    fn test_pin_exact_match() {
+        let check = |names: &[&str], query: &str, found: bool, expected: &[&str]| {
+            let mut crates = names.iter().map(|name| krate(name)).collect::<Vec<_>>();
+            assert_eq!(pin_exact_match(&mut crates, query), found);
+            let names = crates.into_iter().map(|c| c.name).collect::<Vec<_>>();
+            assert_eq!(names, expected);
+        };
+        let crates = ["serde_json", "serde_derive", "serde", "serde_yaml"];
+        let pinned = ["serde", "serde_json", "serde_derive", "serde_yaml"];
+        check(&crates, "serde", true, &pinned);
+        check(
+            &["serde", "serde_json"],
+            "serde",
+            true,
+            &["serde", "serde_json"],
+        );
+        let crates = ["serde_json", "serde_derive"];
+        check(&crates, "serde", false, &crates);
+        check(
+            &["cfg-if-ext", "Cfg-If"],
+            "cfg_if",
+            true,
+            &["Cfg-If", "cfg-if-ext"],
+        );
+        let crates = ["serde_json", "serde-json"];
+        check(&crates, "serde-json", true, &crates);
+        check(&[], "serde", false, &[]);
+    }
+
+    #[test]
+    fn test_is_valid_crate_name() {
+        assert!(is_valid_crate_name("serde_json"));
+        assert!(is_valid_crate_name("cfg-if"));
+        assert!(!is_valid_crate_name(""));
+        assert!(!is_valid_crate_name("serde json"));
+        assert!(!is_valid_crate_name("../summary"));
+    }
+
+    #[test]
+    fn test_deserialize_crates() {
+        // Trimmed response of https://crates.io/api/v1/crates?q=serde_json&per_page=2
+        const RESPONSE: &str = r#"{
+            "crates": [
+                {
+                    "id": "serde_json",
+                    "name": "serde_json",
+                    "updated_at": "2023-10-04T19:52:26.066233+00:00",
+                    "created_at": "2015-08-07T22:03:46.285806+00:00",
+                    "downloads": 295147384,
+                    "recent_downloads": 41735032,
+                    "max_version": "1.0.107",
+                    "max_stable_version": "1.0.107",
+                    "newest_version": "1.0.107",
+                    "description": "A JSON serialization file format",
+                    "homepage": null,
+                    "documentation": "https://docs.rs/serde_json",
+                    "repository": "https://github.com/serde-rs/json",
+                    "license": "MIT OR Apache-2.0",
+                    "exact_match": true
+                },
+                {
+                    "id": "serde_json_any_key",
+                    "name": "serde_json_any_key",
+                    "downloads": 1234,
+                    "recent_downloads": null,
+                    "max_version": "2.0.0",
+                    "description": null,
+                    "documentation": null,
+                    "repository": null,
+                    "license": null
+                }
+            ],
+            "meta": {"total": 1337, "next_page": "?q=serde_json&per_page=2&page=2"}
+        }"#;
+        let resp: Crates = serde_json::from_str(RESPONSE).unwrap();
+        assert_eq!(resp.meta.total, 1337);
+        let descriptions = resp
+            .crates
+            .into_iter()
+            .map(|c| match c.into_inline_query_result() {
+                InlineQueryResult::Article(article) => article.description.unwrap().into_owned(),
+                result => panic!("unexpected result: {result:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            descriptions,
+            [
+                "⬇ 295M (recent 41.7M), updated 2023-10-04 · MIT OR Apache-2.0\n\
+                 A JSON serialization file format",
+                "⬇ 1.2k",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_license() {
+        let krate = Crate {
+            license: Some("MIT & Apache-2.0".to_string()),
+            ..krate("foo")
+        };
+        let article = match krate.into_inline_query_result() {
+            InlineQueryResult::Article(article) => article,
+            result => panic!("unexpected result: {result:?}"),
+        };
+        assert_eq!(article.description.unwrap(), "⬇ 0 · MIT & Apache-2.0");
+        let message = match article.input_message_content {
+            InputMessageContent::Text(content) => content.message_text,
+            content => panic!("unexpected content: {content:?}"),
+        };
+        assert!(message.contains("\nLicense: MIT &amp; Apache-2.0\n"));
+    }
+
+    #[test]
+    fn test_buttons() {
+        let krate = Crate {
+            repository: Some("https://github.com/serde-rs/serde".to_string()),
+            ..krate("serde")
+        };
+        let article = match krate.into_inline_query_result() {
+            InlineQueryResult::Article(article) => article,
+            result => panic!("unexpected result: {result:?}"),
+        };
+        let buttons = article
+            .reply_markup
+            .unwrap()
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .map(|button| match button.pressed {
+                InlineKeyboardButtonPressed::Url(url) => (button.text, url),
+                pressed => panic!("unexpected button: {pressed:?}"),
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("info", "https://crates.io/crates/serde"),
+            ("doc", "https://docs.rs/crate/serde"),
+            ("versions", "https://crates.io/crates/serde/versions"),
+            ("repo", "https://github.com/serde-rs/serde"),
+        ];
+        let expected = expected
+            .iter()
+            .map(|(text, url)| (text.to_string(), url.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(buttons, expected);
+    }
+}
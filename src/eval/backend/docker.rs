@@ -0,0 +1,294 @@
+use super::{
+    acquire_execute_permit, with_timeout, CrateType, ExecutionBackend, Request, Response,
+    EXECUTE_TIMEOUT,
+};
+use crate::eval::execute::Error;
+use crate::eval::parse::Mode;
+use futures::future::{BoxFuture, FutureExt as _};
+use log::{debug, warn};
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::hash::BuildHasher as _;
+use std::io;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::process::Command;
+
+const DEFAULT_IMAGE_PREFIX: &str = "shepmaster/rust-";
+
+/// Backend running code in a local docker container.
+///
+/// It uses the same images as the playground, so that the crates available
+/// there can be used as well.
+pub struct DockerBackend<R = ProcessRunner> {
+    image_prefix: String,
+    runner: R,
+}
+
+impl DockerBackend {
+    pub fn from_env() -> Self {
+        let image_prefix =
+            env::var("EVAL_DOCKER_IMAGE_PREFIX").unwrap_or_else(|_| DEFAULT_IMAGE_PREFIX.into());
+        DockerBackend {
+            image_prefix,
+            runner: ProcessRunner,
+        }
+    }
+}
+
+impl<R: CommandRunner> DockerBackend<R> {
+    fn build_args(&self, req: &Request, name: &str, source: &str) -> Vec<String> {
+        // The image has a crate with popular dependencies at `/playground`.
+        // Update its edition before running the code.
+        let command = match req.crate_type {
+            CrateType::Bin => "run",
+            // The crate in the image is a binary, so just check it instead.
+            CrateType::Lib => "check",
+        };
+        let mut script = format!(
+            r#"sed -i 's/^edition = .*/edition = "{}"/' Cargo.toml && cargo {command}"#,
+            req.edition,
+        );
+        if req.mode == Mode::Release {
+            script.push_str(" --release");
+        }
+        let mount = format!("{source}:/playground/src/main.rs:ro");
+        let image = format!("{}{}", self.image_prefix, req.channel.as_str());
+        [
+            "run",
+            "--rm",
+            "--name",
+            name,
+            "--network",
+            "none",
+            "--memory",
+            "512m",
+            "--volume",
+            &mount,
+            "--workdir",
+            "/playground",
+            &image,
+            "sh",
+            "-c",
+            &script,
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+}
+
+impl<R: CommandRunner> ExecutionBackend for DockerBackend<R> {
+    fn run<'a>(&'a self, req: &'a Request) -> BoxFuture<'a, Result<Response, Error>> {
+        async move {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let name = format!(
+                "rustevalbot-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed),
+            );
+            let dir = TempDir::create(&name)?;
+            let source = dir.0.join("main.rs");
+            fs::write(&source, &req.code)?;
+            let args = self.build_args(req, &name, &source.to_string_lossy());
+            let _permit = acquire_execute_permit().await;
+            debug!("running docker with {:?}", args);
+            let output = with_timeout(*EXECUTE_TIMEOUT, self.runner.run("docker", args)).await;
+            if let Err(Error::Timeout(_)) = output {
+                // Killing the docker client doesn't stop the container.
+                let kill = self.runner.run("docker", vec!["kill".into(), name]);
+                if let Err(e) = kill.await {
+                    warn!("failed to kill container: {:?}", e);
+                }
+            }
+            let output = output?;
+            Ok(Response {
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                success: output.success,
+            })
+        }
+        .boxed()
+    }
+}
+
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Runner of external commands.
+pub trait CommandRunner: Send + Sync {
+    fn run(
+        &self,
+        program: &str,
+        args: Vec<String>,
+    ) -> BoxFuture<'static, io::Result<CommandOutput>>;
+}
+
+pub struct ProcessRunner;
+
+impl CommandRunner for ProcessRunner {
+    fn run(
+        &self,
+        program: &str,
+        args: Vec<String>,
+    ) -> BoxFuture<'static, io::Result<CommandOutput>> {
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        async move {
+            let output = output.await?;
+            Ok(CommandOutput {
+                success: output.status.success(),
+                stdout: output.stdout,
+                stderr: output.stderr,
+            })
+        }
+        .boxed()
+    }
+}
+
+/// Temporary directory which is removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create a directory only accessible by the current user,
+    /// with a random suffix so that others can't predict the path.
+    fn create(prefix: &str) -> io::Result<Self> {
+        let mut builder = DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        loop {
+            let suffix = RandomState::new().hash_one(prefix);
+            let path = env::temp_dir().join(format!("{prefix}-{suffix:016x}"));
+            // Creating fails rather than reusing if the directory exists.
+            match builder.create(&path) {
+                Ok(()) => return Ok(TempDir(path)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.0) {
+            warn!("failed to remove {:?}: {:?}", self.0, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::parse::Channel;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct FakeRunner {
+        /// Command lines including the program.
+        calls: Arc<Mutex<Vec<Vec<String>>>>,
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run(
+            &self,
+            program: &str,
+            args: Vec<String>,
+        ) -> BoxFuture<'static, io::Result<CommandOutput>> {
+            // The source file should be available when the command runs.
+            let source = args
+                .iter()
+                .find_map(|arg| arg.strip_suffix(":/playground/src/main.rs:ro"))
+                .unwrap();
+            let code = fs::read_to_string(source).unwrap();
+            let mut command_line = vec![program.to_string()];
+            command_line.extend(args);
+            self.calls.lock().push(command_line);
+            let output = CommandOutput {
+                success: false,
+                stdout: code.into_bytes(),
+                stderr: b"error".to_vec(),
+            };
+            async move { Ok(output) }.boxed()
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_temp_dir() {
+        use std::os::unix::fs::PermissionsExt as _;
+        let dir = TempDir::create("rustevalbot-test").unwrap();
+        let other = TempDir::create("rustevalbot-test").unwrap();
+        assert_ne!(dir.0, other.0);
+        let mode = fs::metadata(&dir.0).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        let path = dir.0.clone();
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_docker_backend() {
+        let runner = FakeRunner::default();
+        let backend = DockerBackend {
+            image_prefix: "rust-".to_string(),
+            runner: runner.clone(),
+        };
+        let req = Request {
+            channel: Channel::Nightly,
+            edition: "2018",
+            mode: Mode::Release,
+            crate_type: CrateType::Bin,
+            tests: false,
+            backtrace: false,
+            code: "fn main() {}".to_string(),
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let resp = runtime.block_on(backend.run(&req)).unwrap();
+        assert_eq!(resp.stdout, "fn main() {}");
+        assert_eq!(resp.stderr, "error");
+        assert!(!resp.success);
+
+        let calls = runner.calls.lock();
+        assert_eq!(calls.len(), 1);
+        let (program, args) = calls[0].split_first().unwrap();
+        assert_eq!(program, "docker");
+        assert_eq!(
+            &args[..6],
+            &["run", "--rm", "--name", &args[3], "--network", "none"]
+        );
+        assert!(args.contains(&"rust-nightly".to_string()));
+        assert_eq!(
+            args.last().unwrap(),
+            r#"sed -i 's/^edition = .*/edition = "2018"/' Cargo.toml && cargo run --release"#,
+        );
+        // The temporary directory should have been removed.
+        let source = args[9].strip_suffix(":/playground/src/main.rs:ro").unwrap();
+        assert!(!std::path::Path::new(source).exists());
+        let dir = std::path::Path::new(source).parent().unwrap();
+        let dir_name = dir.file_name().unwrap().to_str().unwrap();
+        assert!(dir_name.starts_with(&format!("{}-", args[3])), "{dir_name}");
+        drop(calls);
+
+        let req = Request {
+            mode: Mode::Debug,
+            crate_type: CrateType::Lib,
+            ..req
+        };
+        runtime.block_on(backend.run(&req)).unwrap();
+        let calls = runner.calls.lock();
+        assert!(calls[1].last().unwrap().ends_with(" && cargo check"));
+    }
+}
@@ -0,0 +1,111 @@
+use super::execute::Error;
+use super::parse::{Channel, Mode};
+use futures::future::BoxFuture;
+use log::{debug, info};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::timeout;
+
+mod docker;
+mod playground;
+
+pub use self::docker::DockerBackend;
+pub use self::playground::PlaygroundBackend;
+
+/// Default timeout for running code in seconds.
+const DEFAULT_EXECUTE_TIMEOUT_SECS: u64 = 30;
+
+static EXECUTE_TIMEOUT: Lazy<Duration> = Lazy::new(|| {
+    let secs = env::var("EVAL_TIMEOUT_SECS").map_or(DEFAULT_EXECUTE_TIMEOUT_SECS, |s| {
+        s.parse().expect("EVAL_TIMEOUT_SECS must be a valid number")
+    });
+    Duration::from_secs(secs)
+});
+
+/// Default maximum number of concurrent executions.
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+static EXECUTE_SEMAPHORE: Lazy<Semaphore> = Lazy::new(|| {
+    let permits = env::var("EVAL_MAX_CONCURRENCY").map_or(DEFAULT_MAX_CONCURRENCY, |s| {
+        s.parse()
+            .expect("EVAL_MAX_CONCURRENCY must be a valid number")
+    });
+    Semaphore::new(permits)
+});
+
+/// Wait until we are allowed to run another piece of code.
+async fn acquire_execute_permit() -> SemaphorePermit<'static> {
+    let start = Instant::now();
+    let permit = EXECUTE_SEMAPHORE
+        .acquire()
+        .await
+        .expect("execute semaphore is never closed");
+    let waited = start.elapsed();
+    if waited > Duration::from_secs(1) {
+        debug!("waited {:?} for an execute permit", waited);
+    }
+    permit
+}
+
+/// Backend which compiles and runs the code.
+pub trait ExecutionBackend: Send + Sync {
+    fn run<'a>(&'a self, req: &'a Request) -> BoxFuture<'a, Result<Response, Error>>;
+}
+
+/// Create the backend specified via `EVAL_BACKEND`, defaulting to the playground.
+pub fn from_env(client: Client) -> Box<dyn ExecutionBackend> {
+    match env::var("EVAL_BACKEND").as_deref() {
+        Ok("docker") => {
+            info!("using docker backend");
+            Box::new(DockerBackend::from_env())
+        }
+        Ok("playground") | Err(_) => Box::new(PlaygroundBackend::new(client)),
+        Ok(backend) => panic!("unknown EVAL_BACKEND: {backend}"),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub channel: Channel,
+    pub edition: &'static str,
+    pub mode: Mode,
+    pub crate_type: CrateType,
+    pub tests: bool,
+    pub backtrace: bool,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrateType {
+    Bin,
+    /// Library is only built but not run.
+    Lib,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub stderr: String,
+    pub stdout: String,
+    pub success: bool,
+}
+
+/// Run the given future, failing with [`Error::Timeout`] if it doesn't finish in time.
+pub async fn with_timeout<T, E>(
+    duration: Duration,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, Error>
+where
+    Error: From<E>,
+{
+    match timeout(duration, future).await {
+        Ok(result) => Ok(result?),
+        Err(_elapsed) => Err(Error::Timeout(duration)),
+    }
+}
@@ -0,0 +1,32 @@
+use super::{
+    acquire_execute_permit, with_timeout, ExecutionBackend, Request, Response, EXECUTE_TIMEOUT,
+};
+use crate::eval::execute::Error;
+use futures::future::{BoxFuture, FutureExt as _};
+use reqwest::Client;
+
+/// Backend running code on the Rust Playground.
+pub struct PlaygroundBackend {
+    client: Client,
+}
+
+impl PlaygroundBackend {
+    pub fn new(client: Client) -> Self {
+        PlaygroundBackend { client }
+    }
+}
+
+impl ExecutionBackend for PlaygroundBackend {
+    fn run<'a>(&'a self, req: &'a Request) -> BoxFuture<'a, Result<Response, Error>> {
+        async move {
+            const URL: &str = "https://play.rust-lang.org/execute";
+            let _permit = acquire_execute_permit().await;
+            with_timeout(*EXECUTE_TIMEOUT, async {
+                let resp = self.client.post(URL).json(req).send().await?;
+                resp.error_for_status()?.json().await
+            })
+            .await
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,89 @@
+use super::execute::has_feature_attr;
+use super::parse::{Channel, Flags};
+use std::collections::HashMap;
+use std::env;
+use telegram_types::bot::types::ChatId;
+
+/// Default channels of specific chats.
+pub struct ChatChannels(HashMap<ChatId, Channel>);
+
+impl ChatChannels {
+    /// Read the config from `EVAL_CHAT_CHANNELS`,
+    /// which is a comma-separated list like `-1001234567890=nightly,12345=beta`.
+    pub fn from_env() -> Self {
+        let config = env::var("EVAL_CHAT_CHANNELS").unwrap_or_default();
+        Self::parse(&config)
+    }
+
+    fn parse(config: &str) -> Self {
+        let map = config
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                let (chat, channel) = item
+                    .split_once('=')
+                    .expect("EVAL_CHAT_CHANNELS must be a list of chat=channel");
+                let chat = chat
+                    .trim()
+                    .parse()
+                    .expect("EVAL_CHAT_CHANNELS must use valid chat ids");
+                let channel = match channel.trim() {
+                    "stable" => Channel::Stable,
+                    "beta" => Channel::Beta,
+                    "nightly" => Channel::Nightly,
+                    _ => panic!("EVAL_CHAT_CHANNELS must use valid channels"),
+                };
+                (ChatId(chat), channel)
+            })
+            .collect();
+        ChatChannels(map)
+    }
+
+    /// Get the default channel of the given chat, if it's configured.
+    pub fn get(&self, chat: ChatId) -> Option<Channel> {
+        self.0.get(&chat).copied()
+    }
+}
+
+/// Resolve the channel to run the code with.
+///
+/// Channel specified in the command flags always wins, then nightly if the code
+/// uses unstable features, and then the default channel of the chat.
+pub fn resolve(flags: &Flags, code: &str, chat_default: Option<Channel>) -> Channel {
+    flags
+        .channel
+        .or_else(|| has_feature_attr(code).then_some(Channel::Nightly))
+        .or(chat_default)
+        .unwrap_or(Channel::Stable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let channels = ChatChannels::parse(" -1001234 = nightly, 42=beta,");
+        assert_eq!(channels.get(ChatId(-1001234)), Some(Channel::Nightly));
+        assert_eq!(channels.get(ChatId(42)), Some(Channel::Beta));
+        assert_eq!(channels.get(ChatId(1)), None);
+        assert_eq!(ChatChannels::parse("").get(ChatId(42)), None);
+
+        let code = "fn main() {}";
+        let flags = Flags::default();
+        let beta = channels.get(ChatId(42));
+        assert_eq!(resolve(&flags, code, beta), Channel::Beta);
+        assert_eq!(resolve(&flags, code, None), Channel::Stable);
+        let stable = Flags {
+            channel: Some(Channel::Stable),
+            ..Flags::default()
+        };
+        assert_eq!(resolve(&stable, code, beta), Channel::Stable);
+
+        // Unstable features need nightly regardless of the chat default.
+        let code = "#![feature(test)]\nfn main() {}";
+        assert_eq!(resolve(&flags, code, beta), Channel::Nightly);
+        assert_eq!(resolve(&stable, code, beta), Channel::Stable);
+    }
+}
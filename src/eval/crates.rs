@@ -0,0 +1,125 @@
+use super::backend::with_timeout;
+use crate::messages::{self, MESSAGES};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+const CRATES_URL: &str = "https://play.rust-lang.org/meta/crates";
+/// Page listing crates available on the playground.
+const CRATES_HELP_URL: &str = "https://play.rust-lang.org/help#features-crates";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Versions of crates available on the playground, keyed by crate name with `-` as `_`.
+///
+/// This is updated from the playground at startup.
+static CRATES: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(Default::default);
+
+/// Update the list of crates available on the playground.
+pub async fn update_crates(client: Client) {
+    let result = with_timeout(TIMEOUT, async {
+        let resp = client.get(CRATES_URL).send().await?;
+        resp.error_for_status()?.json::<CratesResponse>().await
+    })
+    .await;
+    match result {
+        Ok(resp) => {
+            info!("{} crates available on playground", resp.crates.len());
+            *CRATES.write() = resp.crates.into_iter().map(|c| (c.id, c.version)).collect();
+        }
+        Err(e) => warn!("failed to get playground crates: {:?}", e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CratesResponse {
+    crates: Vec<CrateInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateInfo {
+    id: String,
+    version: String,
+}
+
+/// Check whether the dependencies are available on the playground,
+/// and generate an error message if not.
+pub fn check_deps(deps: &[(String, Option<String>)]) -> Result<(), String> {
+    let crates = CRATES.read();
+    // Let the compiler tell if we don't know which crates are available.
+    if crates.is_empty() {
+        return Ok(());
+    }
+    check_deps_in(&crates, deps)
+}
+
+fn check_deps_in(
+    crates: &HashMap<String, String>,
+    deps: &[(String, Option<String>)],
+) -> Result<(), String> {
+    for (name, version) in deps {
+        let available = match crates.get(&name.replace('-', "_")) {
+            Some(available) => available,
+            None => {
+                let args: [(&str, &dyn Display); 2] = [("name", name), ("url", &CRATES_HELP_URL)];
+                return Err(messages::fill(MESSAGES.error_unknown_crate, &args));
+            }
+        };
+        if let Some(version) = version {
+            // Partial version like `1.0` matches `1.0.200`.
+            if available != version && !available.starts_with(&format!("{version}.")) {
+                let args: [(&str, &dyn Display); 3] = [
+                    ("name", name),
+                    ("version", version),
+                    ("available", available),
+                ];
+                return Err(messages::fill(MESSAGES.error_crate_version, &args));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_deps() {
+        let crates: HashMap<_, _> = [("serde_json", "1.0.120"), ("cfg_if", "1.0.0")]
+            .into_iter()
+            .map(|(id, version)| (id.to_string(), version.to_string()))
+            .collect();
+        let dep =
+            |name: &str, version: Option<&str>| (name.to_string(), version.map(str::to_string));
+        assert_eq!(check_deps_in(&crates, &[]), Ok(()));
+        assert_eq!(
+            check_deps_in(
+                &crates,
+                &[dep("serde_json", None), dep("cfg-if", Some("1"))]
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_deps_in(&crates, &[dep("serde_json", Some("1.0.120"))]),
+            Ok(())
+        );
+        assert_eq!(
+            check_deps_in(&crates, &[dep("serde_json", None), dep("left-pad", None)]),
+            Err(format!(
+                "error: crate not available on the playground: <code>left-pad</code>, \
+                 see <a href=\"{CRATES_HELP_URL}\">available crates</a>"
+            ))
+        );
+        assert_eq!(
+            check_deps_in(&crates, &[dep("serde_json", Some("1.0.1"))]),
+            Err("error: crate version not available on the playground: \
+                 <code>serde_json@1.0.1</code>, 1.0.120 is available"
+                .to_string())
+        );
+    }
+}
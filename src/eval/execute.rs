@@ -1,84 +1,377 @@
+use super::backend::{with_timeout, CrateType, ExecutionBackend, Request, Response};
 use super::parse::Flags;
+use super::{chat_channel, crates, godbolt, panic};
 use crate::eval::parse::{get_help_message, Channel, Mode};
+use crate::messages::{self, MESSAGES};
+use crate::task_tracker::TaskSpawner;
 use crate::utils;
-use futures::{future, FutureExt as _};
+use derive_more::From;
+use futures::{future, FutureExt as _, TryFutureExt as _};
 use htmlescape::{encode_attribute, encode_minimal};
-use log::{debug, warn};
-use once_cell::sync::Lazy;
+use log::{debug, info, warn};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
 use regex::{Captures, Regex};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{self, Write as _};
 use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Whether the code should be run in 2024 edition by default.
+static EDITION_2024_DEFAULT: Lazy<bool> =
+    Lazy::new(|| match env::var("EVAL_EDITION_2024_DEFAULT").as_deref() {
+        Ok("1") | Ok("true") => true,
+        Ok("0") | Ok("false") | Ok("") | Err(_) => false,
+        Ok(value) => panic!("invalid EVAL_EDITION_2024_DEFAULT: {value}"),
+    });
+
+pub fn init(spawner: Arc<TaskSpawner>) {
+    // The spawner is the same if the bot gets initialized again.
+    let _ = SPAWNER.set(spawner);
+    info!(
+        "default edition: {}",
+        resolve_edition(None, *EDITION_2024_DEFAULT)
+    );
+}
+
+/// Timeout for querying version.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Timeout for formatting code.
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the version of a channel is considered fresh.
+const VERSION_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+static VERSION_CACHE: Lazy<Mutex<HashMap<Channel, (Instant, Version)>>> =
+    Lazy::new(Default::default);
+/// Whether the version of each channel is being refreshed, indexed by `Channel as usize`.
+static VERSION_REFRESHING: [AtomicBool; 3] = [
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+];
+/// Spawner for background tasks, set in `init`.
+static SPAWNER: OnceCell<Arc<TaskSpawner>> = OnceCell::new();
+
+/// Default maximum length in bytes of code to run.
+const DEFAULT_MAX_CODE_LEN: usize = 16 * 1024;
+
+static MAX_CODE_LEN: Lazy<usize> = Lazy::new(|| {
+    env::var("EVAL_MAX_CODE_LEN").map_or(DEFAULT_MAX_CODE_LEN, |s| {
+        s.parse().expect("EVAL_MAX_CODE_LEN must be a valid number")
+    })
+});
+
+/// Output longer than this is sent as a file when requested.
+const MAX_INLINE_OUTPUT_LEN: usize = 4000;
+
+/// Reply of a command.
+pub struct Reply {
+    pub text: String,
+    /// Content to be sent as a separate file.
+    pub file: Option<String>,
+}
+
+impl From<String> for Reply {
+    fn from(text: String) -> Self {
+        Reply { text, file: None }
+    }
+}
+
+#[derive(Debug, From)]
+pub enum Error {
+    Request(reqwest::Error),
+    Io(io::Error),
+    /// The whole request didn't finish within the given duration.
+    #[from(ignore)]
+    Timeout(Duration),
+}
 
 pub fn execute<'p>(
     client: &'p Client,
-    content: &'p str,
+    backend: &'p dyn ExecutionBackend,
+    content: Cow<'p, str>,
     flags: Flags,
+    chat_channel: Option<Channel>,
     is_private: bool,
-) -> Option<impl Future<Output = Result<String, reqwest::Error>> + 'p> {
+) -> Option<impl Future<Output = Result<Reply, Error>> + 'p> {
     Some(if flags.help {
-        future::ok(get_help_message()).left_future().left_future()
+        future::ok(get_help_message().into())
+            .left_future()
+            .left_future()
     } else if flags.version {
         let channel = flags.channel;
-        get_version(client, channel).right_future().left_future()
-    } else if !content.trim().is_empty() {
-        run_code(client, content, flags, is_private).right_future()
-    } else {
+        get_version(client, channel)
+            .map_ok(Reply::from)
+            .right_future()
+            .left_future()
+    } else if content.trim().is_empty() {
         return None;
+    } else if let Some(error) = check_code_len(&content, *MAX_CODE_LEN) {
+        future::ok(error.into()).left_future().left_future()
+    } else if flags.fmt || flags.godbolt {
+        let future = if flags.fmt {
+            format_code(client, content, flags, is_private).left_future()
+        } else {
+            compile_on_godbolt(client, content, flags, is_private).right_future()
+        };
+        future.map_ok(Reply::from).left_future().right_future()
+    } else {
+        run_code(backend, content, flags, chat_channel, is_private)
+            .right_future()
+            .right_future()
     })
 }
 
-async fn get_version(client: &Client, channel: Option<Channel>) -> Result<String, reqwest::Error> {
+/// Generate an error if the code is longer than `max_len` bytes.
+fn check_code_len(code: &str, max_len: usize) -> Option<String> {
+    if code.len() <= max_len {
+        return None;
+    }
+    let to_kb = |len: usize| len.div_ceil(1024);
+    let args: [(&str, &dyn fmt::Display); 2] =
+        [("len", &to_kb(code.len())), ("max", &to_kb(max_len))];
+    Some(messages::fill(MESSAGES.error_code_too_large, &args))
+}
+
+async fn get_version(client: &Client, channel: Option<Channel>) -> Result<String, Error> {
+    if let Some(channel) = channel {
+        let version = get_channel_version(client, channel).await?;
+        return Ok(version.to_string());
+    }
+    // Show versions of all channels if no channel is specified.
+    let (stable, beta, nightly) = future::join3(
+        get_channel_version(client, Channel::Stable),
+        get_channel_version(client, Channel::Beta),
+        get_channel_version(client, Channel::Nightly),
+    )
+    .await;
+    Ok(format_versions([
+        (Channel::Stable, stable),
+        (Channel::Beta, beta),
+        (Channel::Nightly, nightly),
+    ]))
+}
+
+/// Get version of the given channel, which is cached for a while.
+///
+/// Stale version is refreshed in the background, and it is still used if refreshing fails.
+async fn get_channel_version(client: &Client, channel: Channel) -> Result<Version, Error> {
+    let cached = VERSION_CACHE.lock().get(&channel).cloned();
+    if let Some((time, version)) = cached {
+        if time.elapsed() >= VERSION_CACHE_TTL {
+            refresh_channel_version(client, channel);
+        }
+        return Ok(version);
+    }
+    fetch_channel_version(client, channel).await
+}
+
+/// Refresh version of the given channel in the background, unless it's being refreshed already.
+fn refresh_channel_version(client: &Client, channel: Channel) {
+    let spawner = match SPAWNER.get() {
+        Some(spawner) => spawner,
+        None => return,
+    };
+    let refreshing = &VERSION_REFRESHING[channel as usize];
+    if refreshing.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let client = client.clone();
+    spawner.spawn(async move {
+        if let Err(e) = fetch_channel_version(&client, channel).await {
+            warn!("failed to refresh version of {}: {:?}", channel.as_str(), e);
+        }
+        refreshing.store(false, Ordering::Release);
+    });
+}
+
+async fn fetch_channel_version(client: &Client, channel: Channel) -> Result<Version, Error> {
     let url = format!(
         "https://play.rust-lang.org/meta/version/{}",
-        channel.unwrap_or(Channel::Stable).as_str(),
+        channel.as_str(),
     );
-    let resp = client.get(&url).send().await?;
-    let v: Version = resp.error_for_status()?.json().await?;
-    Ok(format!("rustc {} ({:.9} {})", v.version, v.hash, v.date))
+    let version: Version = with_timeout(VERSION_TIMEOUT, async {
+        let resp = client.get(&url).send().await?;
+        resp.error_for_status()?.json().await
+    })
+    .await?;
+    let entry = (Instant::now(), version.clone());
+    VERSION_CACHE.lock().insert(channel, entry);
+    Ok(version)
+}
+
+fn format_versions(versions: [(Channel, Result<Version, Error>); 3]) -> String {
+    let mut result = String::new();
+    for (channel, version) in versions {
+        match version {
+            Ok(version) => writeln!(result, "{version}").unwrap(),
+            Err(e) => {
+                warn!("failed to get version of {}: {:?}", channel.as_str(), e);
+                writeln!(result, "{}: error", channel.as_str()).unwrap();
+            }
+        }
+    }
+    result
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 struct Version {
     date: String,
     hash: String,
     version: String,
 }
 
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rustc {} ({:.9} {})", self.version, self.hash, self.date)
+    }
+}
+
 async fn run_code(
-    client: &Client,
-    code: &str,
+    backend: &dyn ExecutionBackend,
+    code: Cow<'_, str>,
     flags: Flags,
+    chat_channel: Option<Channel>,
     is_private: bool,
-) -> Result<String, reqwest::Error> {
-    let code = utils::normalize_unicode_chars(code);
-    let code = generate_code_to_send(&code, flags.bare);
-    let channel = flags.channel.unwrap_or_else(|| {
-        if has_feature_attr(&code) {
-            Channel::Nightly
-        } else {
-            Channel::Stable
-        }
-    });
+) -> Result<Reply, Error> {
+    if let Err(error) = crates::check_deps(&flags.deps) {
+        return Ok(error.into());
+    }
+    let code = normalize_code(&code, &flags);
+    if let Err(error) = check_env(&code, &flags) {
+        return Ok(error.to_string().into());
+    }
+    let code = generate_code_to_send(&code, &flags);
+    let channel = chat_channel::resolve(&flags, &code, chat_channel);
     let req = Request {
         channel,
-        edition: flags.edition.unwrap_or("2021"),
+        edition: resolve_edition(flags.edition, *EDITION_2024_DEFAULT),
         mode: flags.mode.unwrap_or(Mode::Debug),
-        crate_type: CrateType::Bin,
+        crate_type: if flags.check {
+            CrateType::Lib
+        } else {
+            CrateType::Bin
+        },
         tests: false,
         backtrace: false,
         code,
     };
-    const URL: &str = "https://play.rust-lang.org/execute";
-    let resp = client.post(URL).json(&req).send().await?;
-    let resp = resp.error_for_status()?.json().await?;
-    Ok(generate_result_from_response(resp, channel, is_private))
+    let resp = backend.run(&req).await?;
+    if flags.check && resp.success {
+        return Ok(MESSAGES.compiles_cleanly.to_string().into());
+    }
+    if flags.file && is_private && resp.success && resp.stdout.len() > MAX_INLINE_OUTPUT_LEN {
+        return Ok(Reply {
+            text: MESSAGES.output_sent_as_file.to_string(),
+            file: Some(resp.stdout),
+        });
+    }
+    if let Some(expected) = flags.expect.as_deref().filter(|_| resp.success) {
+        return Ok(generate_expect_result(&resp.stdout, expected, is_private, flags.pretty).into());
+    }
+    if flags.both {
+        if let Some(result) = generate_both_streams_result(&resp, is_private, flags.pretty) {
+            return Ok(result.into());
+        }
+    }
+    let result = generate_result_from_response(resp, channel, is_private, flags.pretty);
+    Ok(result.into())
+}
+
+async fn compile_on_godbolt(
+    client: &Client,
+    code: Cow<'_, str>,
+    flags: Flags,
+    is_private: bool,
+) -> Result<String, Error> {
+    let code = normalize_code(&code, &flags);
+    let edition = resolve_edition(flags.edition, *EDITION_2024_DEFAULT);
+    godbolt::compile(client, &code, edition, &flags, is_private).await
+}
+
+async fn format_code(
+    client: &Client,
+    code: Cow<'_, str>,
+    flags: Flags,
+    is_private: bool,
+) -> Result<String, Error> {
+    const URL: &str = "https://play.rust-lang.org/format";
+    let code = normalize_code(&code, &flags);
+    let channel = flags.channel.unwrap_or(Channel::Stable);
+    let req = FormatRequest {
+        channel,
+        edition: resolve_edition(flags.edition, *EDITION_2024_DEFAULT),
+        code: &code,
+    };
+    let resp = with_timeout(FORMAT_TIMEOUT, async {
+        let resp = client.post(URL).json(&req).send().await?;
+        resp.error_for_status()?.json().await
+    })
+    .await?;
+    Ok(generate_result_from_format_response(
+        resp,
+        channel,
+        is_private,
+        flags.pretty,
+    ))
+}
+
+#[derive(Debug, Serialize)]
+struct FormatRequest<'a> {
+    channel: Channel,
+    edition: &'static str,
+    code: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct FormatResponse {
+    success: bool,
+    code: String,
+    stderr: String,
+}
+
+fn generate_result_from_format_response(
+    resp: FormatResponse,
+    channel: Channel,
+    is_private: bool,
+    pretty: bool,
+) -> String {
+    if !resp.success {
+        // Errors from rustfmt look like those from the compiler.
+        let resp = Response {
+            stderr: resp.stderr,
+            stdout: String::new(),
+            success: false,
+        };
+        return generate_result_from_response(resp, channel, is_private, pretty);
+    }
+    let code = truncate_for_chat(resp.code.trim_end(), "<pre></pre>", is_private, pretty);
+    format!("<pre>{}</pre>", encode_minimal(&code))
+}
+
+fn normalize_code<'a>(code: &'a str, flags: &Flags) -> Cow<'a, str> {
+    if flags.raw {
+        code.into()
+    } else {
+        utils::normalize_unicode_chars(code)
+    }
+}
+
+fn resolve_edition(edition: Option<&'static str>, edition_2024_default: bool) -> &'static str {
+    edition.unwrap_or(if edition_2024_default { "2024" } else { "2021" })
 }
 
 const PRELUDE: &str = include_str!("prelude.res.rs");
 
-fn generate_code_to_send(code: &str, bare: bool) -> String {
-    if bare || code.contains("fn main()") {
+fn generate_code_to_send(code: &str, flags: &Flags) -> String {
+    if flags.bare || has_main_fn(code) {
         return code.to_string();
     }
     macro_rules! template {
@@ -88,56 +381,246 @@ fn generate_code_to_send(code: &str, bare: bool) -> String {
     }
     let (header, body) = extract_code_headers(code);
     debug!("extract: {:?} -> ({:?}, {:?})", code, header, body);
-    let code = if body.contains("println!") || body.contains("print!") {
+    let deps: String = flags
+        .deps
+        .iter()
+        .map(|(name, _)| format!("extern crate {};\n", name.replace('-', "_")))
+        .collect();
+    // Prelude of the dependencies is skipped as if the code imports them.
+    let prelude = deps.clone() + &generate_prelude(&format!("{deps}{code}"));
+    // `set_var` is unsafe since 2024 edition, while the unused unsafe is allowed in others.
+    let env: String = flags
+        .env
+        .iter()
+        .map(|(key, value)| format!("unsafe {{ std::env::set_var({key:?}, {value:?}); }}\n    "))
+        .collect();
+    let stripped_body = utils::strip_comments_and_literals(body);
+    let code = if stripped_body.contains("println!") || stripped_body.contains("print!") {
         format!("{{\n{code}\n}};")
     } else {
         format!(
             template! {
                 // Template below would provide the indent of this line.
-                "println!(\"{{:?}}\", {{",
+                "println!(\"{format}\", {{",
                 "        {code}",
                 "    }});",
             },
+            format = if flags.pretty {
+                "{:#?}"
+            } else if flags.display {
+                "{}"
+            } else {
+                "{:?}"
+            },
             code = body
         )
     };
+    if stripped_body.contains(".await") {
+        // Run async code in a tokio runtime, since main cannot be async.
+        return format!(
+            template! {
+                "#![allow(warnings)]",
+                "{header}",
+                "{prelude}",
+                "fn main() -> Result<(), Box<dyn std::error::Error>> {{",
+                "    {env}tokio::runtime::Runtime::new()?.block_on(async {{",
+                "    {code}",
+                "    Ok::<(), Box<dyn std::error::Error>>(())",
+                "    }})",
+                "}}",
+            },
+            header = header,
+            prelude = prelude,
+            env = env,
+            code = code,
+        );
+    }
     format!(
         template! {
             "#![allow(warnings)]",
             "{header}",
             "{prelude}",
             "fn main() -> Result<(), Box<dyn std::error::Error>> {{",
-            "    {code}",
+            "    {env}{code}",
             "    Ok(())",
             "}}",
         },
         header = header,
-        prelude = PRELUDE,
+        prelude = prelude,
+        env = env,
         code = code,
     )
 }
 
+/// Generate prelude for the given code.
+///
+/// Imports from a crate are skipped if the code imports anything from that crate itself.
+/// Imports in comments and literals don't count.
+fn generate_prelude(code: &str) -> String {
+    static RE_IMPORT: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\b(?:extern\s+crate|use)\s+(?:::)?(\w+)").unwrap());
+    let code = utils::strip_comments_and_literals(code);
+    let imported_crates = RE_IMPORT
+        .captures_iter(&code)
+        .map(|captures| captures.get(1).unwrap().as_str())
+        .filter(|name| !matches!(*name, "std" | "core" | "alloc" | "crate" | "self" | "super"))
+        .collect::<Vec<_>>();
+    if imported_crates.is_empty() {
+        return PRELUDE.to_string();
+    }
+    let mut prelude = String::with_capacity(PRELUDE.len());
+    for line in PRELUDE.lines() {
+        let is_imported = RE_IMPORT
+            .captures(line)
+            .is_some_and(|captures| imported_crates.contains(&captures.get(1).unwrap().as_str()));
+        if !is_imported {
+            prelude.push_str(line);
+            prelude.push('\n');
+        }
+    }
+    prelude
+}
+
+/// Check whether the environment variables can be set for the code.
+///
+/// They are set at the beginning of the generated `main`,
+/// so the code must not be run as is.
+fn check_env(code: &str, flags: &Flags) -> Result<(), &'static str> {
+    if !flags.env.is_empty() && (flags.bare || has_main_fn(code)) {
+        return Err(MESSAGES.error_env_unwrapped);
+    }
+    Ok(())
+}
+
+/// Check whether the code defines `fn main`, outside of comments and literals.
+fn has_main_fn(code: &str) -> bool {
+    static RE_MAIN: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bfn\s+main\s*\(").unwrap());
+    RE_MAIN.is_match(&utils::strip_comments_and_literals(code))
+}
+
 /// Check whether the code includes `#![feature(...)]`
-fn has_feature_attr(code: &str) -> bool {
+pub fn has_feature_attr(code: &str) -> bool {
     static RE_FEATURE: Lazy<Regex> =
         Lazy::new(|| Regex::new(r"#\s*!\s*\[\s*feature\s*\(").unwrap());
     RE_FEATURE.find(code).is_some()
 }
 
-fn generate_result_from_response(resp: Response, channel: Channel, is_private: bool) -> String {
+/// Truncate the output to be sent to the chat,
+/// where `wrapper` is the extra text the output will be sent with.
+fn truncate_for_chat<'a>(
+    output: &'a str,
+    wrapper: &str,
+    is_private: bool,
+    pretty: bool,
+) -> Cow<'a, str> {
+    truncate_part_for_chat(output, wrapper, is_private, pretty, 1)
+}
+
+/// Truncate one of the `parts` outputs sent together to the chat,
+/// each of which gets an equal share of the budget.
+fn truncate_part_for_chat<'a>(
+    output: &'a str,
+    wrapper: &str,
+    is_private: bool,
+    pretty: bool,
+    parts: usize,
+) -> Cow<'a, str> {
+    let max_len = (utils::MAX_MESSAGE_LEN - wrapper.encode_utf16().count()) / parts;
+    if is_private {
+        utils::truncate_output(output, usize::MAX, usize::MAX, max_len)
+    } else {
+        // Pretty output is inherently multi-line, so allow more lines for it.
+        let max_lines = if pretty { 6 } else { 3 };
+        let max_columns = max_lines * 72 / parts;
+        utils::truncate_output(output, max_lines.div_ceil(parts), max_columns, max_len)
+    }
+}
+
+/// Generate result showing both stdout and stderr, if neither of them is empty.
+fn generate_both_streams_result(resp: &Response, is_private: bool, pretty: bool) -> Option<String> {
+    let stdout = resp.stdout.trim();
+    let stderr = resp
+        .stderr
+        .lines()
+        .filter(|line| !is_cargo_status_line(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let stderr = stderr.trim();
+    if stdout.is_empty() || stderr.is_empty() {
+        return None;
+    }
+    const WRAPPER: &str = "<b>stdout:</b>\n<pre></pre>\n<b>stderr:</b>\n<pre></pre>";
+    let stdout = truncate_part_for_chat(stdout, WRAPPER, is_private, pretty, 2);
+    let stderr = truncate_part_for_chat(stderr, WRAPPER, is_private, pretty, 2);
+    Some(format!(
+        "<b>stdout:</b>\n<pre>{}</pre>\n<b>stderr:</b>\n<pre>{}</pre>",
+        encode_minimal(&stdout),
+        encode_minimal(&stderr),
+    ))
+}
+
+/// Whether the line is a status line from cargo rather than from the program.
+fn is_cargo_status_line(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with("Compiling") || line.starts_with("Finished") || line.starts_with("Running")
+}
+
+/// Generate result of comparing the output with the expected text.
+fn generate_expect_result(output: &str, expected: &str, is_private: bool, pretty: bool) -> String {
+    let output = output.trim();
+    let expected = expected.trim();
+    if output == expected {
+        return "✅".to_string();
+    }
+    let mut diff = String::new();
+    let mut expected_lines = expected.lines();
+    let mut output_lines = output.lines();
+    loop {
+        match (expected_lines.next(), output_lines.next()) {
+            (None, None) => break,
+            (Some(e), Some(o)) if e == o => writeln!(diff, "  {e}").unwrap(),
+            (e, o) => {
+                if let Some(e) = e {
+                    writeln!(diff, "- {e}").unwrap();
+                }
+                if let Some(o) = o {
+                    writeln!(diff, "+ {o}").unwrap();
+                }
+            }
+        }
+    }
+    let diff = truncate_for_chat(diff.trim_end(), "❌\n<pre></pre>", is_private, pretty);
+    format!("❌\n<pre>{}</pre>", encode_minimal(&diff))
+}
+
+pub fn generate_result_from_response(
+    resp: Response,
+    channel: Channel,
+    is_private: bool,
+    pretty: bool,
+) -> String {
     if resp.success {
-        let output = resp.stdout.trim();
-        let output = if is_private {
-            output.into()
+        // Output of `print!` may not end with a newline, which gets lost by trimming.
+        let note = if !resp.stdout.is_empty() && !resp.stdout.ends_with('\n') {
+            format!("\n<em>{}</em>", MESSAGES.no_trailing_newline)
         } else {
-            const MAX_LINES: usize = 3;
-            const MAX_TOTAL_COLUMNS: usize = MAX_LINES * 72;
-            utils::truncate_output(output, MAX_LINES, MAX_TOTAL_COLUMNS)
+            String::new()
         };
+        let wrapper = format!("<pre></pre>{note}");
+        let output = truncate_for_chat(resp.stdout.trim(), &wrapper, is_private, pretty);
         if output.is_empty() {
-            return "(no output)".to_string();
+            return MESSAGES.no_output.to_string();
         }
-        return format!("<pre>{}</pre>", encode_minimal(&output));
+        // The end of truncated output isn't shown anyway.
+        let note = match output {
+            Cow::Borrowed(_) => note.as_str(),
+            Cow::Owned(_) => "",
+        };
+        return format!("<pre>{}</pre>{note}", encode_minimal(&output));
+    }
+
+    if let Some(message) = explain_kill(&resp.stderr) {
+        return message.to_string();
     }
 
     static RE_ERROR: Lazy<Regex> = Lazy::new(|| Regex::new(r"^error\[(E\d{4})\]:").unwrap());
@@ -146,11 +629,7 @@ fn generate_result_from_response(resp: Response, channel: Channel, is_private: b
     let mut return_line: Option<&str> = None;
     for line in resp.stderr.split('\n') {
         let line = line.trim();
-        if line.starts_with("Compiling")
-            || line.starts_with("Finished")
-            || line.starts_with("Running")
-            || line.is_empty()
-        {
+        if is_cargo_status_line(line) || line.is_empty() {
             continue;
         }
         if line.starts_with("error") {
@@ -184,35 +663,60 @@ fn generate_result_from_response(resp: Response, channel: Channel, is_private: b
             let url = format!("https://github.com/rust-lang/rust/issues/{issue_num}");
             format!(r#"(see issue <a href="{url}">#{issue_num}</a>)"#)
         });
-        format!("{line}")
+        let line = match classify_failure(&resp.stderr) {
+            Some(Failure::Compile) => messages::fill(MESSAGES.compile_error, &[("error", &line)]),
+            Some(Failure::Runtime) => messages::fill(MESSAGES.runtime_error, &[("error", &line)]),
+            None => line.into_owned(),
+        };
+        match panic::explain(&resp.stderr) {
+            Some(explanation) => format!("{line}\n{explanation}"),
+            None => line,
+        }
     } else {
-        "(nothing??)".to_string()
+        MESSAGES.nothing.to_string()
     }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct Request {
-    channel: Channel,
-    edition: &'static str,
-    mode: Mode,
-    crate_type: CrateType,
-    tests: bool,
-    backtrace: bool,
-    code: String,
+#[derive(Debug, PartialEq)]
+enum Failure {
+    /// The code failed to build.
+    Compile,
+    /// The program failed after it started running.
+    Runtime,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "lowercase")]
-enum CrateType {
-    Bin,
+/// Tell from the stderr of cargo at which stage the run failed.
+fn classify_failure(stderr: &str) -> Option<Failure> {
+    let mut is_building = false;
+    for line in stderr.lines().map(str::trim) {
+        if line.starts_with("Running") {
+            return Some(Failure::Runtime);
+        }
+        if line.starts_with("Compiling") || line.starts_with("Checking") {
+            is_building = true;
+        } else if is_building && line.starts_with("error") {
+            return Some(Failure::Compile);
+        }
+    }
+    None
 }
 
-#[derive(Debug, Deserialize)]
-struct Response {
-    stderr: String,
-    stdout: String,
-    success: bool,
+/// Explain why the program is killed, if it is killed by the resource limits of the playground.
+fn explain_kill(stderr: &str) -> Option<&'static str> {
+    // The playground runs the program via `timeout --signal=KILL`,
+    // and the shell reports the signal which terminated it.
+    // Being killed by the timeout looks the same as being killed by the OOM killer,
+    // so only a failed allocation tells that the memory limit is hit.
+    let killed_line = stderr
+        .lines()
+        .find(|line| line.contains("timeout --signal=KILL"))?;
+    if stderr.contains("memory allocation of ") {
+        Some(MESSAGES.killed_memory)
+    } else if killed_line.contains("Killed") {
+        Some(MESSAGES.killed)
+    } else {
+        None
+    }
 }
 
 fn extract_code_headers(code: &str) -> (&str, &str) {
@@ -284,6 +788,448 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_generate_code_with_crate_prelude() {
+        let code = generate_code_to_send(
+            "(1..5).tuple_windows::<(_, _)>().count()",
+            &Flags::default(),
+        );
+        assert!(code.contains("\nextern crate itertools;\n"));
+        assert!(code.contains("\nuse itertools::Itertools;\n"));
+        assert!(code.contains("\nuse rand::prelude::*;\n"));
+        assert!(code.contains("\nuse regex::Regex;\n"));
+        assert!(code.contains("(1..5).tuple_windows::<(_, _)>().count()"));
+
+        // Prelude of a crate is skipped when the code imports from it.
+        let code = generate_code_to_send(
+            "use itertools::Itertools as _; (1..5).tuple_windows::<(_, _)>().count()",
+            &Flags::default(),
+        );
+        assert!(!code.contains("extern crate itertools;"));
+        assert_eq!(code.matches("use itertools::").count(), 1);
+        assert!(code.contains("\nuse regex::Regex;\n"));
+        let code = generate_code_to_send("extern crate rand;\nrandom::<u8>()", &Flags::default());
+        assert_eq!(code.matches("extern crate rand;").count(), 1);
+        assert!(!code.contains("use rand::prelude::*;"));
+        assert!(code.contains("\nuse regex::Regex;\n"));
+
+        // Imports in literals and comments don't affect the prelude.
+        let code = generate_code_to_send(
+            "\"use itertools;\".len() // use regex::Regex;",
+            &Flags::default(),
+        );
+        assert!(code.contains("\nextern crate itertools;\n"));
+        assert!(code.contains("\nuse regex::Regex;\n"));
+    }
+
+    #[test]
+    fn test_generate_code_with_await() {
+        let code = generate_code_to_send("async { 1 }.await", &Flags::default());
+        assert!(code.contains("tokio::runtime::Runtime::new()?.block_on(async {\n"));
+        assert!(code.contains("println!(\"{:?}\", {\n        async { 1 }.await\n    });"));
+        assert!(code.contains("Ok::<(), Box<dyn std::error::Error>>(())"));
+        // Code without await is not wrapped in a runtime.
+        let code = generate_code_to_send("1 + 1", &Flags::default());
+        assert!(!code.contains("block_on"));
+        // Explicit main and bare code are left untouched.
+        let code = "#[tokio::main]\nasync fn main() {\n    async { 1 }.await;\n}";
+        assert_eq!(generate_code_to_send(code, &Flags::default()), code);
+        let bare = Flags {
+            bare: true,
+            ..Flags::default()
+        };
+        assert_eq!(
+            generate_code_to_send("async { 1 }.await", &bare),
+            "async { 1 }.await"
+        );
+    }
+
+    #[test]
+    fn test_generate_code_result_format() {
+        let code = generate_code_to_send("vec![1]", &Flags::default());
+        assert!(code.contains("println!(\"{:?}\", {\n        vec![1]\n    });"));
+        let pretty = Flags {
+            pretty: true,
+            ..Flags::default()
+        };
+        let code = generate_code_to_send("vec![1]", &pretty);
+        assert!(code.contains("println!(\"{:#?}\", {\n        vec![1]\n    });"));
+        let display = Flags {
+            display: true,
+            ..Flags::default()
+        };
+        let code = generate_code_to_send("format_args!(\"a\")", &display);
+        assert!(code.contains("println!(\"{}\", {\n        format_args!(\"a\")\n    });"));
+    }
+
+    #[test]
+    fn test_generate_code_with_deps() {
+        let flags = Flags {
+            deps: vec![
+                ("serde_json".to_string(), None),
+                ("itertools".to_string(), Some("0.12".to_string())),
+            ],
+            ..Flags::default()
+        };
+        let code = generate_code_to_send("#![allow(unused)]\njson!(1)", &flags);
+        assert!(code
+            .contains("#![allow(unused)]\n\nextern crate serde_json;\nextern crate itertools;\n"));
+        assert_eq!(code.matches("extern crate itertools;").count(), 1);
+        assert!(!code.contains("use itertools::Itertools;"));
+        let flags = Flags {
+            deps: vec![("cfg-if".to_string(), None)],
+            ..Flags::default()
+        };
+        let code = generate_code_to_send("1", &flags);
+        assert!(code.contains("\nextern crate cfg_if;\n"));
+    }
+
+    #[test]
+    fn test_generate_code_with_env() {
+        let flags = Flags {
+            env: vec![
+                ("RUST_LOG".to_string(), "debug".to_string()),
+                ("QUOTE".to_string(), "a\"b".to_string()),
+            ],
+            ..Flags::default()
+        };
+        let code = generate_code_to_send("1", &flags);
+        let expected = "fn main() -> Result<(), Box<dyn std::error::Error>> {\n    \
+                        unsafe { std::env::set_var(\"RUST_LOG\", \"debug\"); }\n    \
+                        unsafe { std::env::set_var(\"QUOTE\", \"a\\\"b\"); }\n    \
+                        println!(";
+        assert!(code.contains(expected), "{code}");
+    }
+
+    #[test]
+    fn test_check_env() {
+        let env_flags = Flags {
+            env: vec![("RUST_LOG".to_string(), "debug".to_string())],
+            ..Flags::default()
+        };
+        assert_eq!(check_env("1", &env_flags), Ok(()));
+        let main = "fn main() { println!(\"hi\"); }";
+        assert_eq!(check_env(main, &Flags::default()), Ok(()));
+        assert_eq!(
+            check_env(main, &env_flags),
+            Err(MESSAGES.error_env_unwrapped)
+        );
+        let bare_flags = Flags {
+            bare: true,
+            ..env_flags
+        };
+        assert_eq!(
+            check_env("1", &bare_flags),
+            Err(MESSAGES.error_env_unwrapped)
+        );
+    }
+
+    #[test]
+    fn test_generate_code_ignores_literals_and_comments() {
+        // `fn main()` in string doesn't prevent wrapping.
+        let code = generate_code_to_send("\"fn main() is the entry\".len()", &Flags::default());
+        assert!(code.contains("println!(\"{:?}\", {\n        \"fn main() is the entry\".len()\n"));
+        // `println!` in comment doesn't prevent printing the result.
+        let code = generate_code_to_send("1 // println!(\"{}\", 1)", &Flags::default());
+        assert!(code.contains("println!(\"{:?}\", {\n        1 // println!(\"{}\", 1)\n"));
+        // `.await` in string doesn't need a runtime.
+        let code = generate_code_to_send("\".await\"", &Flags::default());
+        assert!(!code.contains("block_on"));
+
+        // Real `fn main()` and `println!` keep working.
+        let code = "fn main() { println!(\"hi\"); }";
+        assert_eq!(generate_code_to_send(code, &Flags::default()), code);
+        let code = generate_code_to_send("println!(\"hi\")", &Flags::default());
+        assert!(!code.contains("{:?}"));
+        assert!(code.contains("println!(\"hi\")"));
+    }
+
+    #[test]
+    fn test_normalize_invisible_chars() {
+        let code = normalize_code("let\u{200b} x = 1;\nx", &Flags::default());
+        let code = generate_code_to_send(&code, &Flags::default());
+        assert!(code.contains("let x = 1;\n"), "{code}");
+        assert!(!code.contains('\u{200b}'));
+
+        // Invisible characters in string literals are removed as well, as users
+        // can hardly have put them there intentionally. Use `--raw` to keep them.
+        let code = "\"a\u{200b}b\".len()";
+        assert_eq!(normalize_code(code, &Flags::default()), "\"ab\".len()");
+        let raw = Flags {
+            raw: true,
+            ..Flags::default()
+        };
+        assert_eq!(normalize_code(code, &raw), code);
+
+        // `--raw` bypasses all of the normalization.
+        let code = "x = １ – ２；…\u{a0}“";
+        assert_eq!(normalize_code(code, &Flags::default()), "x = 1 - 2;... \"");
+        assert_eq!(normalize_code(code, &raw), code);
+    }
+
+    #[test]
+    fn test_format_versions() {
+        let version = |version: &str| Version {
+            date: "2024-07-21".to_string(),
+            hash: "051478957371ee0084a7c0913941d2a8c4757bb9".to_string(),
+            version: version.to_string(),
+        };
+        let result = format_versions([
+            (Channel::Stable, Ok(version("1.80.0"))),
+            (Channel::Beta, Ok(version("1.81.0-beta.1"))),
+            (Channel::Nightly, Err(Error::Timeout(VERSION_TIMEOUT))),
+        ]);
+        assert_eq!(
+            result,
+            "rustc 1.80.0 (051478957 2024-07-21)\n\
+             rustc 1.81.0-beta.1 (051478957 2024-07-21)\n\
+             nightly: error\n"
+        );
+    }
+
+    #[test]
+    fn test_version_ignores_chat_channel() {
+        for (channel, version) in [
+            (Channel::Stable, "1.80.0"),
+            (Channel::Beta, "1.81.0-beta.1"),
+            (Channel::Nightly, "1.82.0-nightly"),
+        ] {
+            let version = Version {
+                date: "2024-07-21".to_string(),
+                hash: "051478957371ee0084a7c0913941d2a8c4757bb9".to_string(),
+                version: version.to_string(),
+            };
+            VERSION_CACHE
+                .lock()
+                .insert(channel, (Instant::now(), version));
+        }
+        let client = Client::new();
+        let backend = crate::eval::backend::PlaygroundBackend::new(client.clone());
+        let flags = Flags {
+            version: true,
+            ..Flags::default()
+        };
+        let future = execute(
+            &client,
+            &backend,
+            "".into(),
+            flags,
+            Some(Channel::Nightly),
+            false,
+        );
+        let reply = futures::executor::block_on(future.unwrap()).unwrap();
+        // Versions of all channels are shown even if the chat defaults to one.
+        assert_eq!(reply.text.lines().count(), 3, "{}", reply.text);
+    }
+
+    #[test]
+    fn test_generate_expect_result() {
+        assert_eq!(generate_expect_result("3\n", " 3 ", false, false), "✅");
+        assert_eq!(
+            generate_expect_result("[1, 2]", "[1, 3]", false, false),
+            "❌\n<pre>- [1, 3]\n+ [1, 2]</pre>"
+        );
+        assert_eq!(
+            generate_expect_result("a\n<b>\nc", "a\nb", true, false),
+            "❌\n<pre>  a\n- b\n+ &lt;b&gt;\n+ c</pre>"
+        );
+        // Diff is truncated in group chats.
+        assert_eq!(
+            generate_expect_result("1\n2\n3", "", false, false),
+            "❌\n<pre>+ 1\n+ 2\n+ 3</pre>"
+        );
+        assert_eq!(
+            generate_expect_result("1\n2\n3\n4", "", false, false),
+            "❌\n<pre>+ 1\n+ 2\n+ 3...</pre>"
+        );
+    }
+
+    #[test]
+    fn test_check_code_len() {
+        let code = "a".repeat(16 * 1024);
+        assert_eq!(check_code_len(&code, 16 * 1024), None);
+        assert_eq!(
+            check_code_len(&format!("{code}b"), 16 * 1024).unwrap(),
+            "error: code too large (17 KB > 16 KB limit)"
+        );
+        let code = "a".repeat(37 * 1024);
+        assert_eq!(
+            check_code_len(&code, 16 * 1024).unwrap(),
+            "error: code too large (37 KB > 16 KB limit)"
+        );
+    }
+
+    #[test]
+    fn test_generate_result_from_format_response() {
+        let resp = FormatResponse {
+            success: true,
+            code: "fn f() -> Vec<u8> {\n    vec![]\n}\n".to_string(),
+            stderr: String::new(),
+        };
+        assert_eq!(
+            generate_result_from_format_response(resp, Channel::Stable, true, false),
+            "<pre>fn f() -&gt; Vec&lt;u8&gt; {\n    vec![]\n}</pre>"
+        );
+        let resp = FormatResponse {
+            success: false,
+            code: String::new(),
+            stderr: "error: expected one of `!` or `::`, found `f`\n \
+                     --> <stdin>:1:4\n"
+                .to_string(),
+        };
+        assert_eq!(
+            generate_result_from_format_response(resp, Channel::Stable, true, false),
+            "error: expected one of <code>!</code> or <code>::</code>, found <code>f</code>"
+        );
+    }
+
+    #[test]
+    fn test_no_trailing_newline() {
+        let response = |stdout: &str| Response {
+            stderr: String::new(),
+            stdout: stdout.to_string(),
+            success: true,
+        };
+        let note = format!("\n<em>{}</em>", MESSAGES.no_trailing_newline);
+        // `print!("abc")`
+        assert_eq!(
+            generate_result_from_response(response("abc"), Channel::Stable, true, false),
+            format!("<pre>abc</pre>{note}")
+        );
+        assert_eq!(
+            generate_result_from_response(response("abc\n"), Channel::Stable, true, false),
+            "<pre>abc</pre>"
+        );
+        assert_eq!(
+            generate_result_from_response(response(""), Channel::Stable, true, false),
+            MESSAGES.no_output
+        );
+        // Truncated output doesn't show its end, so there is no note.
+        let output = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13";
+        assert_eq!(
+            generate_result_from_response(response(output), Channel::Stable, false, false),
+            "<pre>1\n2\n3...</pre>"
+        );
+    }
+
+    #[test]
+    fn test_classify_failure() {
+        let type_error = "   Compiling playground v0.0.1 (/playground)\n\
+            error[E0308]: mismatched types\n \
+            --> src/main.rs:2:18\n\
+            error: could not compile `playground` (bin \"playground\") due to 1 previous error\n";
+        assert_eq!(classify_failure(type_error), Some(Failure::Compile));
+        let link_error = "   Compiling playground v0.0.1 (/playground)\n\
+            error: linking with `cc` failed: exit status: 1\n  \
+            = note: undefined reference to `foo'\n";
+        assert_eq!(classify_failure(link_error), Some(Failure::Compile));
+        let check_error = "    Checking playground v0.0.1 (/playground)\n\
+            error[E0425]: cannot find value `x` in this scope\n";
+        assert_eq!(classify_failure(check_error), Some(Failure::Compile));
+        let panic = "   Compiling playground v0.0.1 (/playground)\n\
+            \x20   Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.62s\n\
+            \x20    Running `target/debug/playground`\n\
+            thread 'main' panicked at src/main.rs:2:5:\n\
+            explicit panic\n";
+        assert_eq!(classify_failure(panic), Some(Failure::Runtime));
+        let error_output = "   Compiling playground v0.0.1 (/playground)\n\
+            \x20    Running `target/debug/playground`\n\
+            Error: \"oops\"\n";
+        assert_eq!(classify_failure(error_output), Some(Failure::Runtime));
+        // Not from cargo, e.g. rustfmt.
+        assert_eq!(classify_failure("error: expected item, found `x`\n"), None);
+
+        let resp = Response {
+            stderr: type_error.to_string(),
+            stdout: String::new(),
+            success: false,
+        };
+        assert!(
+            generate_result_from_response(resp, Channel::Stable, false, false)
+                .starts_with("⚠ compile error: error<a href=")
+        );
+        let resp = Response {
+            stderr: panic.to_string(),
+            stdout: String::new(),
+            success: false,
+        };
+        let result = generate_result_from_response(resp, Channel::Stable, false, false);
+        assert!(result.starts_with("💥 runtime error: thread "));
+    }
+
+    #[test]
+    fn test_explain_kill() {
+        let infinite_loop = "   Compiling playground v0.0.1 (/playground)\n\
+            \x20   Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.62s\n\
+            \x20    Running `target/debug/playground`\n\
+            /playground/tools/entrypoint.sh: line 11:     8 Killed                  \
+            timeout --signal=KILL ${timeout} \"$@\"\n";
+        assert_eq!(explain_kill(infinite_loop), Some(MESSAGES.killed));
+        let oom = "   Compiling playground v0.0.1 (/playground)\n\
+            \x20   Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.51s\n\
+            \x20    Running `target/debug/playground`\n\
+            memory allocation of 68719476736 bytes failed\n\
+            /playground/tools/entrypoint.sh: line 11:     8 Aborted                 \
+            timeout --signal=KILL ${timeout} \"$@\"\n";
+        assert_eq!(explain_kill(oom), Some(MESSAGES.killed_memory));
+        let panic = "   Compiling playground v0.0.1 (/playground)\n\
+            thread 'main' panicked at src/main.rs:2:5:\n\
+            explicit panic\n";
+        assert_eq!(explain_kill(panic), None);
+        let resp = Response {
+            stderr: infinite_loop.to_string(),
+            stdout: String::new(),
+            success: false,
+        };
+        assert_eq!(
+            generate_result_from_response(resp, Channel::Stable, false, false),
+            MESSAGES.killed
+        );
+    }
+
+    #[test]
+    fn test_generate_both_streams_result() {
+        let response = |stdout: &str, stderr: &str| Response {
+            stdout: stdout.to_string(),
+            stderr: stderr.to_string(),
+            success: true,
+        };
+        let stderr = "   Compiling playground v0.0.1 (/playground)\n\
+                      \x20   Finished dev [unoptimized + debuginfo] target(s) in 0.5s\n\
+                      \x20    Running `target/debug/playground`\n\
+                      a < b\n";
+        assert_eq!(
+            generate_both_streams_result(&response("out\n", stderr), false, false).unwrap(),
+            "<b>stdout:</b>\n<pre>out</pre>\n<b>stderr:</b>\n<pre>a &lt; b</pre>"
+        );
+        assert_eq!(
+            generate_both_streams_result(&response("", stderr), false, false),
+            None
+        );
+        let cargo_only = "   Compiling playground v0.0.1 (/playground)\n";
+        assert_eq!(
+            generate_both_streams_result(&response("out", cargo_only), false, false),
+            None
+        );
+        // Each stream gets half of the lines in group chats.
+        let result =
+            generate_both_streams_result(&response("1\n2\n3", "4\n5\n6"), false, false).unwrap();
+        assert!(result.contains("<pre>1\n2...</pre>"), "{result}");
+        assert!(result.contains("<pre>4\n5...</pre>"), "{result}");
+        let result =
+            generate_both_streams_result(&response("1\n2\n3", "4\n5\n6"), true, false).unwrap();
+        assert!(result.contains("<pre>1\n2\n3</pre>"), "{result}");
+    }
+
+    #[test]
+    fn test_resolve_edition() {
+        assert_eq!(resolve_edition(None, false), "2021");
+        assert_eq!(resolve_edition(None, true), "2024");
+        assert_eq!(resolve_edition(Some("2018"), false), "2018");
+        assert_eq!(resolve_edition(Some("2018"), true), "2018");
+        assert_eq!(resolve_edition(Some("2021"), true), "2021");
+    }
+
     #[test]
     fn test_has_feature_attr() {
         assert!(has_feature_attr("#![feature(test)]\nfn main() {}"));
@@ -295,4 +1241,23 @@ mod tests {
         ));
         assert!(!has_feature_attr("#![cfg(x)]"));
     }
+
+    #[test]
+    fn test_has_main_fn() {
+        assert!(has_main_fn("fn main() {}"));
+        assert!(has_main_fn("fn main () {}"));
+        assert!(has_main_fn("fn\tmain(\n) {}"));
+        assert!(has_main_fn(
+            "fn main() -> Result<(), Box<dyn Error>> { Ok(()) }"
+        ));
+        assert!(has_main_fn("#[tokio::main]\nasync fn main() {}"));
+        assert!(!has_main_fn("fn main_loop() {}"));
+        assert!(!has_main_fn("fn domain() {}"));
+        assert!(!has_main_fn("// fn main() {}\n1"));
+        assert!(!has_main_fn("\"fn main()\""));
+
+        // Wrapping is skipped for these.
+        let code = "fn main () -> Result<(), String> { Ok(()) }";
+        assert_eq!(generate_code_to_send(code, &Flags::default()), code);
+    }
 }
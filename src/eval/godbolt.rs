@@ -0,0 +1,277 @@
+use super::backend::{with_timeout, Response};
+use super::execute::{generate_result_from_response, Error};
+use super::parse::{Channel, Flags, Mode};
+use htmlescape::encode_attribute;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use reqwest::header::ACCEPT;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const API_BASE: &str = "https://godbolt.org/api";
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Compiler id of the latest stable rustc on Compiler Explorer.
+///
+/// This is updated from the compilers API at startup.
+static STABLE_COMPILER: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new("r1800".to_string()));
+
+fn get_compiler_id(channel: Channel) -> String {
+    match channel {
+        Channel::Stable => STABLE_COMPILER.read().clone(),
+        Channel::Beta => "beta".to_string(),
+        Channel::Nightly => "nightly".to_string(),
+    }
+}
+
+/// Update the compiler id of stable channel from Compiler Explorer.
+pub async fn update_compilers(client: Client) {
+    let url = format!("{API_BASE}/compilers/rust?fields=id,semver");
+    let result = with_timeout(TIMEOUT, async {
+        let resp = client.get(&url).header(ACCEPT, "application/json").send();
+        resp.await?
+            .error_for_status()?
+            .json::<Vec<Compiler>>()
+            .await
+    })
+    .await;
+    match result {
+        Ok(compilers) => match find_latest_stable(&compilers) {
+            Some(id) => {
+                info!("godbolt stable compiler: {}", id);
+                *STABLE_COMPILER.write() = id.to_string();
+            }
+            None => warn!("no stable compiler found on godbolt"),
+        },
+        Err(e) => warn!("failed to get godbolt compilers: {:?}", e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Compiler {
+    id: String,
+    semver: String,
+}
+
+fn find_latest_stable(compilers: &[Compiler]) -> Option<&str> {
+    compilers
+        .iter()
+        .filter(|c| {
+            // Stable releases have ids like `r1800`.
+            let digits = c.id.strip_prefix('r').unwrap_or("");
+            !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+        })
+        .filter_map(|c| {
+            let version = c
+                .semver
+                .split('.')
+                .map(|n| n.parse::<u32>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            Some((version, c.id.as_str()))
+        })
+        .max()
+        .map(|(_, id)| id)
+}
+
+/// Compile the code on Compiler Explorer and reply with the assembly.
+pub async fn compile(
+    client: &Client,
+    code: &str,
+    edition: &'static str,
+    flags: &Flags,
+    is_private: bool,
+) -> Result<String, Error> {
+    let channel = flags.channel.unwrap_or(Channel::Stable);
+    let compiler = get_compiler_id(channel);
+    let mut user_arguments = format!("--edition {edition}");
+    if flags.mode == Some(Mode::Release) {
+        user_arguments.push_str(" -O");
+    }
+    let req = CompileRequest {
+        source: code,
+        options: CompileOptions {
+            user_arguments: &user_arguments,
+            filters: Filters {
+                directives: true,
+                comment_only: true,
+                labels: true,
+                demangle: true,
+                intel: true,
+                library_code: true,
+            },
+        },
+    };
+    let url = format!("{API_BASE}/compiler/{compiler}/compile");
+    let resp: CompileResponse = with_timeout(TIMEOUT, async {
+        let resp = client.post(&url).header(ACCEPT, "application/json");
+        resp.json(&req)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    })
+    .await?;
+    let mut result =
+        generate_result_from_response(resp.into_response(), channel, is_private, false);
+    match shorten(client, &compiler, &user_arguments, code).await {
+        Ok(url) => {
+            let url = encode_attribute(&url);
+            result.push_str(&format!("\n<a href=\"{url}\">view on godbolt</a>"));
+        }
+        Err(e) => warn!("failed to create godbolt link: {:?}", e),
+    }
+    Ok(result)
+}
+
+async fn shorten(
+    client: &Client,
+    compiler: &str,
+    options: &str,
+    source: &str,
+) -> Result<String, Error> {
+    let req = ShortenerRequest {
+        sessions: [Session {
+            id: 1,
+            language: "rust",
+            source,
+            compilers: [SessionCompiler {
+                id: compiler,
+                options,
+            }],
+        }],
+    };
+    let url = format!("{API_BASE}/shortener");
+    let resp: ShortenerResponse = with_timeout(TIMEOUT, async {
+        let resp = client.post(&url).header(ACCEPT, "application/json");
+        resp.json(&req)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    })
+    .await?;
+    Ok(resp.url)
+}
+
+#[derive(Debug, Serialize)]
+struct CompileRequest<'a> {
+    source: &'a str,
+    options: CompileOptions<'a>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompileOptions<'a> {
+    user_arguments: &'a str,
+    filters: Filters,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Filters {
+    directives: bool,
+    comment_only: bool,
+    labels: bool,
+    demangle: bool,
+    intel: bool,
+    library_code: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompileResponse {
+    code: i32,
+    #[serde(default)]
+    asm: Vec<Line>,
+    #[serde(default)]
+    stderr: Vec<Line>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Line {
+    text: String,
+}
+
+impl CompileResponse {
+    fn into_response(self) -> Response {
+        fn join_lines(lines: Vec<Line>) -> String {
+            let mut result = String::new();
+            for line in lines {
+                result.push_str(&line.text);
+                result.push('\n');
+            }
+            result
+        }
+        Response {
+            success: self.code == 0,
+            stdout: join_lines(self.asm),
+            stderr: join_lines(self.stderr),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ShortenerRequest<'a> {
+    sessions: [Session<'a>; 1],
+}
+
+#[derive(Debug, Serialize)]
+struct Session<'a> {
+    id: u32,
+    language: &'a str,
+    source: &'a str,
+    compilers: [SessionCompiler<'a>; 1],
+}
+
+#[derive(Debug, Serialize)]
+struct SessionCompiler<'a> {
+    id: &'a str,
+    options: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShortenerResponse {
+    url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_latest_stable() {
+        let compilers: Vec<Compiler> = serde_json::from_str(
+            r#"[
+                {"id": "r1690", "semver": "1.69.0"},
+                {"id": "nightly", "semver": "nightly"},
+                {"id": "r1800", "semver": "1.80.0"},
+                {"id": "r1790", "semver": "1.79.0"},
+                {"id": "beta", "semver": "beta"},
+                {"id": "gccrs-snapshot", "semver": "(GCC master)"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(find_latest_stable(&compilers), Some("r1800"));
+        assert_eq!(find_latest_stable(&compilers[..2]), Some("r1690"));
+        assert_eq!(find_latest_stable(&compilers[4..]), None);
+    }
+
+    #[test]
+    fn test_compile_response() {
+        let resp: CompileResponse = serde_json::from_str(
+            r#"{
+                "code": 0,
+                "asm": [{"text": "square:"}, {"text": "        mov     eax, edi"}],
+                "stderr": []
+            }"#,
+        )
+        .unwrap();
+        let resp = resp.into_response();
+        assert!(resp.success);
+        assert_eq!(resp.stdout, "square:\n        mov     eax, edi\n");
+        assert_eq!(resp.stderr, "");
+    }
+}
@@ -0,0 +1,67 @@
+use crate::messages::MESSAGES;
+
+/// Generate code for `/layout` with the given expression,
+/// or an error message if it can't be run.
+pub fn generate_code(expr: &str, bare: bool) -> Result<String, &'static str> {
+    // The probe is a list of statements which needs to be wrapped in `main`.
+    if bare {
+        return Err(MESSAGES.error_layout_bare);
+    }
+    generate_probe(expr).ok_or(MESSAGES.layout_usage)
+}
+
+/// Generate code which prints the type and memory layout of the given expression.
+///
+/// Returns `None` if the expression is empty.
+fn generate_probe(expr: &str) -> Option<String> {
+    let expr = expr.trim().trim_end_matches(';').trim_end();
+    if expr.is_empty() {
+        return None;
+    }
+    Some(format!(
+        concat!(
+            "let value = {{\n",
+            "    {expr}\n",
+            "}};\n",
+            "println!(\n",
+            "    \"{{}}: size = {{}}, align = {{}}\",\n",
+            "    type_name_of_val(&value),\n",
+            "    size_of_val(&value),\n",
+            "    align_of_val(&value),\n",
+            ");",
+        ),
+        expr = expr,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_probe() {
+        let expected = "let value = {\n\
+                        \x20   Some(1u8)\n\
+                        };\n\
+                        println!(\n\
+                        \x20   \"{}: size = {}, align = {}\",\n\
+                        \x20   type_name_of_val(&value),\n\
+                        \x20   size_of_val(&value),\n\
+                        \x20   align_of_val(&value),\n\
+                        );";
+        assert_eq!(generate_probe("Some(1u8)").as_deref(), Some(expected));
+        assert_eq!(generate_probe(" Some(1u8); \n").as_deref(), Some(expected));
+        assert_eq!(generate_probe(""), None);
+        assert_eq!(generate_probe(" ;\n"), None);
+    }
+
+    #[test]
+    fn test_generate_code() {
+        assert_eq!(
+            generate_code("1u8", false),
+            Ok(generate_probe("1u8").unwrap())
+        );
+        assert_eq!(generate_code("", false), Err(MESSAGES.layout_usage));
+        assert_eq!(generate_code("1u8", true), Err(MESSAGES.error_layout_bare));
+    }
+}
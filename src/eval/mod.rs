@@ -1,147 +1,516 @@
+use self::backend::ExecutionBackend;
+use self::chat_channel::ChatChannels;
+use self::rate_limit::RateLimiter;
 use self::record::RecordService;
 use crate::bot::Bot;
-use crate::eval::parse::Command;
+use crate::eval::parse::{Command, CommandKind, Flags, ParseError};
+use crate::messages::{self, MESSAGES};
+use crate::mute::{self, MuteService};
+use crate::shutdown::Shutdown;
+use crate::task_tracker::TaskSpawner;
 use crate::utils;
-use futures::future;
+use futures::future::{self, AbortHandle, Abortable, Either};
+use futures::{pin_mut, FutureExt};
+use htmlescape::encode_minimal;
 use log::{debug, info, warn};
 use reqwest::Client;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write as _;
 use std::future::Future;
 use std::sync::Arc;
-use telegram_types::bot::types::{Message, UpdateContent, UpdateId};
+use std::time::{Duration, Instant};
+use telegram_types::bot::types::{ChatId, Message, MessageId, UpdateContent, UpdateId};
 use tokio::sync::Mutex;
+use tokio::time::{interval, sleep, sleep_until};
 
+mod backend;
+mod chat_channel;
+mod crates;
 mod execute;
+mod godbolt;
+mod layout;
+mod panic;
 mod parse;
+mod rate_limit;
 mod record;
 
+/// Default number of evals a user can run per minute.
+const DEFAULT_RATE_LIMIT: u32 = 5;
+/// Default maximum number of records kept for editing.
+const DEFAULT_MAX_RECORDS: usize = 5000;
+/// Seconds after which the placeholder is updated with the elapsed time.
+const PROGRESS_SECS: [u64; 2] = [5, 15];
+/// Interval to flush records to disk, so that they survive crashes.
+const RECORDS_FLUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Time to wait for further edits before handling an edit.
+const EDIT_DEBOUNCE: Duration = Duration::from_millis(750);
+/// File to persist chats where the bot is muted.
+const MUTE_LIST_FILE: &str = "mute_list.json";
+
 /// Eval bot.
 pub struct EvalBot {
     bot: Bot,
     client: Client,
-    records: Mutex<RecordService>,
+    backend: Box<dyn ExecutionBackend>,
+    records: Arc<Mutex<RecordService>>,
+    rate_limiter: Mutex<RateLimiter>,
+    chat_channels: ChatChannels,
+    mutes: Mutex<MuteService>,
+    /// Handle to abort the pending edit of each message, tagged with its generation.
+    pending_edits: Mutex<HashMap<MessageId, (u64, AbortHandle)>>,
 }
 
 impl EvalBot {
     /// Create new eval bot instance.
-    pub fn new(client: Client, bot: Bot) -> Self {
-        let records = Mutex::new(RecordService::init());
+    ///
+    /// The records are flushed to disk periodically, and once shutdown is triggered.
+    pub fn new(client: Client, bot: Bot, spawner: &Arc<TaskSpawner>, shutdown: &Shutdown) -> Self {
+        let max_records = env::var("EVAL_MAX_RECORDS").map_or(DEFAULT_MAX_RECORDS, |s| {
+            s.parse().expect("EVAL_MAX_RECORDS must be a valid number")
+        });
+        let records = Arc::new(Mutex::new(RecordService::init(max_records)));
+        let stop_signal = shutdown.register();
+        let records_clone = records.clone();
+        spawner.spawn(async move {
+            let periodic_flush = async {
+                let mut interval = interval(RECORDS_FLUSH_INTERVAL);
+                // The first tick completes immediately.
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    records_clone.lock().await.flush();
+                }
+            };
+            pin_mut!(periodic_flush);
+            // We don't care if the sender has gone, flush anyway.
+            future::select(stop_signal, periodic_flush).await;
+            records_clone.lock().await.flush();
+            debug!("records flushed");
+        });
+        let rate_limit = env::var("EVAL_RATE_LIMIT").map_or(DEFAULT_RATE_LIMIT, |s| {
+            s.parse().expect("EVAL_RATE_LIMIT must be a valid number")
+        });
+        let rate_limiter = Mutex::new(RateLimiter::new(rate_limit));
+        let backend = backend::from_env(client.clone());
+        let chat_channels = ChatChannels::from_env();
+        execute::init(spawner.clone());
+        spawner.spawn(godbolt::update_compilers(client.clone()));
+        spawner.spawn(crates::update_crates(client.clone()));
         info!("EvalBot authorized as @{}", bot.username);
         EvalBot {
             bot,
             client,
+            backend,
             records,
+            rate_limiter,
+            chat_channels,
+            mutes: Mutex::new(MuteService::init(MUTE_LIST_FILE)),
+            pending_edits: Default::default(),
         }
     }
 
     /// Handle the update.
-    pub async fn handle_update(self: Arc<Self>, id: UpdateId, content: UpdateContent) {
+    pub async fn handle_update(self: Arc<Self>, _: UpdateId, content: UpdateContent) {
         match content {
-            UpdateContent::Message(message) => self.handle_message(id, &message).await,
-            UpdateContent::EditedMessage(message) => self.handle_edit_message(id, &message).await,
+            UpdateContent::Message(message) => {
+                if mute::may_handle_command(&self.bot, &self.mutes, &message).await {
+                    return;
+                }
+                if self.may_handle_history_command(&message).await {
+                    return;
+                }
+                if self.is_muted(&message).await {
+                    return;
+                }
+                self.handle_message(&message).await
+            }
+            UpdateContent::EditedMessage(message) => {
+                if self.is_muted(&message).await {
+                    return;
+                }
+                self.handle_edit_message(&message).await
+            }
             _ => {}
         }
     }
 
-    async fn handle_message(&self, id: UpdateId, message: &Message) {
+    async fn is_muted(&self, message: &Message) -> bool {
+        self.mutes.lock().await.is_muted(message.chat.id)
+    }
+
+    /// Handle `/history` command, which lists recent commands of the sender.
+    ///
+    /// Returns whether the message is such a command.
+    async fn may_handle_history_command(&self, message: &Message) -> bool {
+        let text = match &message.text {
+            Some(text) => text,
+            None => return false,
+        };
+        let count = match parse::parse_history_command(text, self.bot.username) {
+            Some(count) => count,
+            None => return false,
+        };
+        let from = match &message.from {
+            Some(from) => from,
+            None => return true,
+        };
+        // Commands may come from groups, so don't show them anywhere else.
+        let text = if !utils::is_message_from_private_chat(message) {
+            MESSAGES.history_private_only.to_string()
+        } else {
+            let records = self.records.lock().await;
+            let history = records.history(from.id, count);
+            if history.is_empty() {
+                MESSAGES.history_empty.to_string()
+            } else {
+                let mut text = String::new();
+                for (msg, reply, excerpt) in history {
+                    let reply = reply.map_or("-".to_string(), |reply| reply.0.to_string());
+                    let excerpt = encode_minimal(excerpt);
+                    writeln!(text, "{} → {reply}: <code>{excerpt}</code>", msg.0).unwrap();
+                }
+                text
+            }
+        };
+        let request = self.bot.send_message(message.chat.id, text);
+        if let Err(err) = request.execute().await {
+            warn!("error sending: {:?}", err);
+        }
+        true
+    }
+
+    async fn handle_message(&self, message: &Message) {
         self.records.lock().await.clear_old_records(&message.date);
-        let reply_future = match self.prepare_command(id, message) {
-            Some(future) => async { generate_reply(future.await) },
+        self.rate_limiter
+            .lock()
+            .await
+            .clear_old_buckets(&message.date);
+        let (reply_future, command, quiet) = match self.prepare_command(message).await {
+            Some(Prepared {
+                reply,
+                command,
+                quiet,
+            }) => (async { generate_reply(reply.await) }, command, quiet),
             None => return,
         };
+        let chat_id = message.chat.id;
+        if let Err(wait) = self.check_rate_limit(message).await {
+            debug!("rate limited");
+            let text = messages::fill(MESSAGES.rate_limited, &[("wait", &wait)]);
+            let request = self.bot.reply_message(chat_id, message.message_id, text);
+            if let Err(err) = request.execute().await {
+                warn!("error sending: {:?}", err);
+            }
+            return;
+        }
         let msg_id = message.message_id;
         let date = message.date.clone();
-        self.records.lock().await.push_record(msg_id, date);
-        let chat_id = message.chat.id;
+        {
+            let mut records = self.records.lock().await;
+            records.push_record(msg_id, date);
+            if let (Some(from), Some(text)) = (&message.from, &message.text) {
+                records.set_sender(msg_id, from.id, text);
+            }
+            if let Some(command) = command {
+                records.set_last_command(msg_id, chat_id, command);
+            }
+        }
+        if quiet {
+            self.send_reply_quietly(chat_id, msg_id, reply_future).await;
+            return;
+        }
 
         // Send the placeholder reply.
         let placeholder_future = async {
-            let text = "<em>Processing...</em>";
-            let request = self.bot.send_message(chat_id, text);
-            match request.execute().await {
+            let text = format!("<em>{}</em>", MESSAGES.processing);
+            let send = || self.bot.send_message(chat_id, text.as_str()).execute();
+            let mut result = send().await;
+            if let Err(err) = &result {
+                // Only retry if the placeholder can't have been sent, to avoid duplicating it.
+                if err.is_safe_to_retry() {
+                    warn!("error sending, retrying: {:?}", err);
+                    result = send().await;
+                }
+            }
+            match result {
                 Ok(msg) => {
                     let reply_id = msg.message_id;
-                    debug!("{}> placeholder sent as {}", id.0, reply_id.0);
+                    debug!("placeholder sent as {}", reply_id.0);
                     self.records.lock().await.set_reply(msg_id, reply_id);
                     Ok(reply_id)
                 }
                 Err(err) => {
-                    warn!("{}> error sending: {:?}", id.0, err);
+                    warn!("error sending: {:?}", err);
                     Err(())
                 }
             }
         };
 
-        // Update the reply to the real result.
-        let (placeholder, reply) = future::join(placeholder_future, reply_future).await;
+        // Send the placeholder while the reply is being generated.
+        let start = Instant::now();
+        pin_mut!(placeholder_future);
+        pin_mut!(reply_future);
+        let first_done = future::select(placeholder_future, &mut reply_future).await;
+        let (placeholder, reply) = match first_done {
+            Either::Left((placeholder, _)) => (placeholder, None),
+            Either::Right((reply, placeholder_future)) => (placeholder_future.await, Some(reply)),
+        };
         let reply_id = match placeholder {
             Ok(reply_id) => reply_id,
             Err(()) => return,
         };
+        let reply = match reply {
+            Some(reply) => reply,
+            None => {
+                let wait = self.wait_for_reply(chat_id, msg_id, reply_id, start, reply_future);
+                wait.await
+            }
+        };
 
-        let reply = reply.trim_matches(char::is_whitespace);
-        debug!("{}> updating reply: {:?}", id.0, reply);
-        let request = self.bot.edit_message(chat_id, reply_id, reply);
+        // Update the reply to the real result.
+        // The original message has generation 0.
+        if !self.is_current_edit(msg_id, 0).await {
+            debug!("superseded by edit");
+            return;
+        }
+
+        let text = reply.text.trim_matches(char::is_whitespace);
+        debug!("updating reply: {:?}", text);
+        let request = self.bot.edit_message(chat_id, reply_id, text);
         match request.execute().await {
-            Ok(_) => debug!("{}> reply sent", id.0),
-            Err(err) => warn!("{}> error updating: {:?}", id.0, err),
+            Ok(_) => debug!("reply sent"),
+            Err(err) => warn!("error updating: {:?}", err),
+        }
+        self.send_file(chat_id, msg_id, reply.file).await;
+    }
+
+    /// Send the reply once it's ready, without a placeholder.
+    async fn send_reply_quietly(
+        &self,
+        chat_id: ChatId,
+        msg_id: MessageId,
+        reply_future: impl Future<Output = execute::Reply>,
+    ) {
+        let reply = reply_future.await;
+        // The original message has generation 0.
+        if !self.is_current_edit(msg_id, 0).await {
+            debug!("superseded by edit");
+            return;
+        }
+        let text = reply.text.trim_matches(char::is_whitespace);
+        debug!("sending reply: {:?}", text);
+        match self.bot.send_message(chat_id, text).execute().await {
+            Ok(msg) => {
+                debug!("reply sent as {}", msg.message_id.0);
+                self.records.lock().await.set_reply(msg_id, msg.message_id);
+            }
+            Err(err) => {
+                warn!("error sending: {:?}", err);
+                return;
+            }
+        }
+        self.send_file(chat_id, msg_id, reply.file).await;
+    }
+
+    /// Wait for the reply, meanwhile updating the placeholder with the elapsed time.
+    async fn wait_for_reply(
+        &self,
+        chat_id: ChatId,
+        msg_id: MessageId,
+        reply_id: MessageId,
+        start: Instant,
+        reply_future: impl Future<Output = execute::Reply>,
+    ) -> execute::Reply {
+        pin_mut!(reply_future);
+        for secs in PROGRESS_SECS {
+            tokio::select! {
+                reply = &mut reply_future => return reply,
+                _ = sleep_until((start + Duration::from_secs(secs)).into()) => {}
+            }
+            // Don't clobber the reply of a later edit.
+            if !self.is_current_edit(msg_id, 0).await {
+                break;
+            }
+            let text = format!(
+                "<em>{}</em>",
+                messages::fill(MESSAGES.processing_elapsed, &[("secs", &secs)])
+            );
+            let request = self.bot.edit_message(chat_id, reply_id, text).execute();
+            pin_mut!(request);
+            // Finish the intermediate edit even if the reply is ready,
+            // so that it cannot arrive after the final edit.
+            let (result, reply) = tokio::select! {
+                result = &mut request => (result, None),
+                reply = &mut reply_future => (request.await, Some(reply)),
+            };
+            match result {
+                Ok(_) => debug!("placeholder updated at {}s", secs),
+                Err(err) => warn!("error updating placeholder: {:?}", err),
+            }
+            if let Some(reply) = reply {
+                return reply;
+            }
         }
+        reply_future.await
     }
 
-    async fn handle_edit_message(&self, id: UpdateId, message: &Message) {
+    async fn handle_edit_message(&self, message: &Message) {
         let msg_id = message.message_id;
-        let reply_id = match self.records.lock().await.find_reply(msg_id) {
-            Some(reply) => reply,
-            None => return,
+        let (reply_id, generation) = {
+            let mut records = self.records.lock().await;
+            match records.find_reply(msg_id) {
+                Some(reply) => (reply, records.bump_generation(msg_id).unwrap()),
+                None => return,
+            }
         };
+        // Cancel the superseded edit, so that it doesn't keep the playground busy.
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let previous = self
+            .pending_edits
+            .lock()
+            .await
+            .insert(msg_id, (generation, abort_handle));
+        if let Some((_, previous)) = previous {
+            previous.abort();
+        }
+        let edit = self.handle_edit(message, reply_id, generation);
+        if Abortable::new(edit, abort_registration).await.is_err() {
+            debug!("aborted by later edit");
+        }
+        let mut pending_edits = self.pending_edits.lock().await;
+        if pending_edits.get(&msg_id).map(|(g, _)| *g) == Some(generation) {
+            pending_edits.remove(&msg_id);
+        }
+    }
+
+    async fn handle_edit(&self, message: &Message, reply_id: MessageId, generation: u64) {
+        let msg_id = message.message_id;
+        // Wait a bit so that a burst of edits collapses into the last one.
+        sleep(EDIT_DEBOUNCE).await;
+        if !self.is_current_edit(msg_id, generation).await {
+            debug!("superseded by later edit");
+            return;
+        }
         let chat_id = message.chat.id;
-        let reply_future = match self.prepare_command(id, message) {
-            Some(future) => async { generate_reply(future.await) },
+        let prepared = match self.prepare_command(message).await {
+            Some(prepared) => prepared,
             None => {
                 // Delete reply if the new command is invalid.
-                debug!("{}> deleting", id.0);
+                debug!("deleting");
                 self.records.lock().await.remove_reply(msg_id);
                 let request = self.bot.delete_message(chat_id, reply_id);
                 match request.execute().await {
-                    Ok(_) => debug!("{}> deleted", id.0),
-                    Err(err) => warn!("{}> error deleting: {:?}", id.0, err),
+                    Ok(_) => debug!("deleted"),
+                    Err(err) => warn!("error deleting: {:?}", err),
                 }
                 return;
             }
         };
+        // Edits re-run the code, so they count towards the limit as well.
+        if let Err(wait) = self.check_rate_limit(message).await {
+            debug!("rate limited");
+            let text = messages::fill(MESSAGES.rate_limited, &[("wait", &wait)]);
+            let request = self.bot.edit_message(chat_id, reply_id, text);
+            match request.execute().await {
+                Ok(_) => debug!("updated"),
+                Err(err) if err.is_message_not_modified() => debug!("unchanged"),
+                Err(err) => warn!("error updating: {:?}", err),
+            }
+            return;
+        }
+        let Prepared {
+            reply,
+            command,
+            quiet,
+        } = prepared;
+        if let Some(command) = command {
+            let mut records = self.records.lock().await;
+            records.set_last_command(msg_id, chat_id, command);
+        }
+        let reply_future = async { generate_reply(reply.await) };
 
         // Update the reply with a placeholder.
         let placeholder_future = async {
-            let text = "<em>Updating...</em>";
+            if quiet {
+                return;
+            }
+            let text = format!("<em>{}</em>", MESSAGES.updating);
             let request = self.bot.edit_message(chat_id, reply_id, text);
             match request.execute().await {
-                Ok(_) => debug!("{}> placeholder updated", id.0),
-                Err(err) => warn!("{}> error updating placeholder: {:?}", id.0, err),
+                Ok(_) => debug!("placeholder updated"),
+                // The placeholder may be left by a superseded edit.
+                Err(err) if err.is_message_not_modified() => {}
+                Err(err) => warn!("error updating placeholder: {:?}", err),
             }
         };
 
         // Update the reply to the real result.
         let (_placeholder, reply) = future::join(placeholder_future, reply_future).await;
-        let reply = reply.trim_matches(char::is_whitespace);
-        debug!("{}> updating: {:?}", id.0, reply);
-        let request = self.bot.edit_message(chat_id, reply_id, reply);
+        if !self.is_current_edit(msg_id, generation).await {
+            debug!("superseded by later edit");
+            return;
+        }
+        let text = reply.text.trim_matches(char::is_whitespace);
+        debug!("updating: {:?}", text);
+        let request = self.bot.edit_message(chat_id, reply_id, text);
+        match request.execute().await {
+            Ok(_) => debug!("updated"),
+            Err(err) if err.is_message_not_modified() => debug!("unchanged"),
+            Err(err) => warn!("error updating: {:?}", err),
+        }
+        self.send_file(chat_id, msg_id, reply.file).await;
+    }
+
+    async fn is_current_edit(&self, msg_id: MessageId, generation: u64) -> bool {
+        let records = self.records.lock().await;
+        records.is_current_generation(msg_id, generation)
+    }
+
+    /// Send the output as a file in reply to the command message.
+    ///
+    /// It's only sent once for each message, so that edits don't pile up files.
+    async fn send_file(&self, chat_id: ChatId, msg_id: MessageId, file: Option<String>) {
+        let file = match file {
+            Some(file) => file,
+            None => return,
+        };
+        if !self.records.lock().await.mark_file_sent(msg_id) {
+            debug!("file already sent");
+            return;
+        }
+        let request = self.bot.send_document(chat_id, msg_id, "output.txt", file);
         match request.execute().await {
-            Ok(_) => debug!("{}> updated", id.0),
-            Err(err) => warn!("{}> error updating: {:?}", id.0, err),
+            Ok(_) => debug!("file sent"),
+            Err(err) => warn!("error sending file: {:?}", err),
         }
     }
 
-    fn prepare_command<'p>(
+    async fn check_rate_limit(&self, message: &Message) -> Result<(), u64> {
+        let from = match &message.from {
+            Some(from) => from,
+            None => return Ok(()),
+        };
+        // Admin is not limited.
+        if from.id == *crate::ADMIN_ID {
+            return Ok(());
+        }
+        let date = rate_limit::message_time(message);
+        self.rate_limiter.lock().await.check(from.id, date)
+    }
+
+    /// Prepare the reply to the command in the message.
+    async fn prepare_command<'p>(
         &'p self,
-        id: UpdateId,
         message: &'p Message,
-    ) -> Option<impl Future<Output = Result<String, reqwest::Error>> + 'p> {
+    ) -> Option<Prepared<impl Future<Output = Result<execute::Reply, execute::Error>> + 'p>> {
         // Don't care about messages not sent from a user.
         let from = message.from.as_ref()?;
         // Don't care about non-text messages.
         let command = message.text.as_ref()?;
         debug!(
-            "{}> received from {}({}): [{}] {:?}",
-            id.0,
+            "received from {}({}): [{}] {:?}",
             from.username
                 .as_ref()
                 .map_or("[no username]", |s| s.as_str()),
@@ -149,36 +518,145 @@ impl EvalBot {
             message.message_id.0,
             command
         );
-        let is_private = utils::is_message_from_private_chat(message);
-        let Command {
-            bot_name,
-            flags,
-            content,
-        } = parse::parse_command(command)?;
-        if let Some(name) = bot_name {
-            if name != self.bot.username {
-                return None;
+        let is_for_other_bot =
+            |bot_name: Option<&str>| bot_name.is_some_and(|name| name != self.bot.username);
+        let parsed = match parse::parse_command(command) {
+            Ok(parsed) => parsed,
+            Err(ParseError::NotCommand) => return None,
+            Err(ParseError::Invalid { bot_name, near, .. }) => {
+                if is_for_other_bot(bot_name) {
+                    return None;
+                }
+                let reply =
+                    messages::fill(MESSAGES.error_parse, &[("near", &encode_minimal(near))]);
+                return Some(Prepared {
+                    reply: future::ok(reply.into()).left_future(),
+                    command: None,
+                    quiet: false,
+                });
             }
+        };
+        if is_for_other_bot(parsed.bot_name) {
+            return None;
+        }
+        let quiet = parsed.flags.quiet;
+        if !parsed.flags.again {
+            let future =
+                self.execute_command(message, parsed.kind, parsed.flags, parsed.content.into());
+            return future.map(|future| Prepared {
+                reply: future.right_future(),
+                command: Some(command.clone()),
+                quiet,
+            });
         }
-        execute::execute(&self.client, content, flags, is_private)
+        if parsed.has_extra_for_again() {
+            let reply = MESSAGES.error_again_extra.to_string().into();
+            return Some(Prepared {
+                reply: future::ok(reply).left_future(),
+                command: None,
+                quiet,
+            });
+        }
+        let last_command = {
+            let records = self.records.lock().await;
+            records.last_command(message.chat.id).map(str::to_string)
+        };
+        let last = last_command
+            .as_deref()
+            .and_then(|c| parse::parse_command(c).ok());
+        let Some(Command {
+            kind,
+            flags,
+            content,
+            ..
+        }) = last
+        else {
+            let reply = MESSAGES.no_last_command.to_string().into();
+            return Some(Prepared {
+                reply: future::ok(reply).left_future(),
+                command: None,
+                quiet,
+            });
+        };
+        let quiet = quiet || flags.quiet;
+        let future = self.execute_command(message, kind, flags, content.to_string().into());
+        future.map(|future| Prepared {
+            reply: future.right_future(),
+            command: last_command,
+            quiet,
+        })
+    }
+
+    fn execute_command<'p>(
+        &'p self,
+        message: &Message,
+        kind: CommandKind,
+        flags: Flags,
+        content: Cow<'p, str>,
+    ) -> Option<impl Future<Output = Result<execute::Reply, execute::Error>> + 'p> {
+        let is_private = utils::is_message_from_private_chat(message);
+        let content = match kind {
+            CommandKind::Eval => content,
+            CommandKind::Layout => match layout::generate_code(&content, flags.bare) {
+                Ok(code) => code.into(),
+                Err(message) => {
+                    let reply = message.to_string().into();
+                    return Some(future::ok(reply).left_future());
+                }
+            },
+        };
+        let chat_channel = self.chat_channels.get(message.chat.id);
+        let future = execute::execute(
+            &self.client,
+            &*self.backend,
+            content,
+            flags,
+            chat_channel,
+            is_private,
+        );
+        future.map(FutureExt::right_future)
     }
 }
 
-fn generate_reply(reply: Result<String, reqwest::Error>) -> String {
+/// Command prepared to be run.
+struct Prepared<F> {
+    /// Future generating the reply.
+    reply: F,
+    /// Command text to be remembered for `--again`.
+    command: Option<String>,
+    /// Whether to send the reply without a placeholder.
+    quiet: bool,
+}
+
+fn generate_reply(reply: Result<execute::Reply, execute::Error>) -> execute::Reply {
     match reply {
         Ok(reply) => reply,
-        Err(err) => {
+        Err(err) => generate_error_reply(err).into(),
+    }
+}
+
+fn generate_error_reply(err: execute::Error) -> String {
+    match err {
+        execute::Error::Timeout(duration) => messages::fill(
+            MESSAGES.error_eval_timeout,
+            &[("secs", &duration.as_secs())],
+        ),
+        execute::Error::Request(err) => {
             if err.is_builder() {
-                "error: builder error".into()
+                MESSAGES.error_builder.into()
             } else if err.is_redirect() {
-                "error: failed to request".into()
+                MESSAGES.error_request.into()
             } else if err.is_timeout() {
-                "error: timeout".into()
+                MESSAGES.error_timeout.into()
             } else if let Some(status) = err.status() {
-                format!("error: status code: {status}")
+                messages::fill(MESSAGES.error_status_code, &[("status", &status)])
             } else {
-                "error: unknown error".into()
+                MESSAGES.error_unknown.into()
             }
         }
+        execute::Error::Io(err) => {
+            warn!("failed to run code: {:?}", err);
+            MESSAGES.error_run.into()
+        }
     }
 }
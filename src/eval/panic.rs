@@ -0,0 +1,79 @@
+/// Explanations of common panic messages, in HTML.
+static EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "index out of bounds",
+        "Indexing panics when the index is not less than the length. \
+         Use <code>get</code> to get an <code>Option</code> instead.",
+    ),
+    (
+        "called `Option::unwrap()` on a `None` value",
+        "<code>unwrap</code> panics on <code>None</code>. \
+         Use <code>match</code>, <code>if let</code>, or <code>?</code> to handle it.",
+    ),
+    (
+        "called `Result::unwrap()` on an `Err` value",
+        "<code>unwrap</code> panics on <code>Err</code>. \
+         Use <code>match</code>, <code>if let</code>, or <code>?</code> to handle it.",
+    ),
+    (
+        "with overflow",
+        "Integer overflow panics in debug mode. Use <code>wrapping_*</code>, \
+         <code>checked_*</code>, or <code>saturating_*</code> methods if it is intended.",
+    ),
+    (
+        "attempt to divide by zero",
+        "Integer division by zero always panics. \
+         Use <code>checked_div</code> to get an <code>Option</code> instead.",
+    ),
+    (
+        "already borrowed",
+        "<code>RefCell</code> panics when borrowed mutably while another borrow is alive. \
+         Use <code>try_borrow_mut</code> or shorten the borrows.",
+    ),
+    (
+        "is not a char boundary",
+        "Strings are indexed by byte, and slicing must not split a UTF-8 character. \
+         Use <code>char_indices</code> to find valid boundaries.",
+    ),
+];
+
+/// Explain the panic in the given stderr, if it is a recognized one.
+pub fn explain(stderr: &str) -> Option<&'static str> {
+    let mut lines = stderr
+        .lines()
+        .skip_while(|line| !line.contains("panicked at"));
+    // Older versions of Rust put the panic message in the same line as `panicked at`,
+    // while newer versions put it in the next line.
+    let panic_line = lines.next()?;
+    let message_line = lines.next().unwrap_or("");
+    EXPLANATIONS
+        .iter()
+        .find(|(pattern, _)| panic_line.contains(pattern) || message_line.contains(pattern))
+        .map(|(_, explanation)| *explanation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let stderr = "   Compiling playground v0.0.1 (/playground)\n\
+                      \x20   Finished dev [unoptimized + debuginfo] target(s) in 0.5s\n\
+                      \x20    Running `target/debug/playground`\n\
+                      thread 'main' panicked at src/main.rs:4:5:\n\
+                      index out of bounds: the len is 3 but the index is 5\n\
+                      note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n";
+        assert_eq!(explain(stderr), Some(EXPLANATIONS[0].1));
+        let stderr = "thread 'main' panicked at \
+                      'called `Option::unwrap()` on a `None` value', src/main.rs:4:5\n";
+        assert_eq!(explain(stderr), Some(EXPLANATIONS[1].1));
+        let stderr = "thread 'main' panicked at src/main.rs:4:5:\n\
+                      explicit panic\n";
+        assert_eq!(explain(stderr), None);
+        assert_eq!(
+            explain("error[E0425]: cannot find value `x` in this scope\n"),
+            None
+        );
+    }
+}
@@ -1,160 +1,420 @@
-use combine::error::StringStreamError;
 use combine::parser::char::{alpha_num, space, spaces, string};
 use combine::parser::choice::{choice, optional};
 use combine::parser::combinator::attempt;
 use combine::parser::range::recognize;
-use combine::parser::repeat::{many, skip_many1};
-use combine::parser::token::{eof, token};
+use combine::parser::repeat::{many, skip_many, skip_many1};
+use combine::parser::sequence::between;
+use combine::parser::token::{satisfy, token, value};
 use combine::parser::Parser;
 use serde::Serialize;
 use std::fmt::Write as _;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct Command<'a> {
+    pub kind: CommandKind,
     pub bot_name: Option<&'a str>,
     pub flags: Flags,
     pub content: &'a str,
 }
 
-pub fn parse_command(command: &str) -> Option<Command<'_>> {
+impl Command<'_> {
+    /// Whether the command has anything besides `--again` and `--quiet`,
+    /// which would be ignored when running the last command again.
+    pub fn has_extra_for_again(&self) -> bool {
+        let other_flags = Flags {
+            again: false,
+            quiet: false,
+            ..self.flags.clone()
+        };
+        !self.content.trim().is_empty() || other_flags != Flags::default()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CommandKind {
+    /// Run the given code.
+    Eval,
+    /// Show the memory layout of the given expression.
+    Layout,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ParseError<'a> {
+    /// The text is not a command.
+    NotCommand,
+    /// The command is malformed.
+    Invalid {
+        bot_name: Option<&'a str>,
+        /// Byte position where the error happens.
+        position: usize,
+        /// The text near the error.
+        near: &'a str,
+    },
+}
+
+pub fn parse_command(command: &str) -> Result<Command<'_>, ParseError<'_>> {
+    let command_name = choice((
+        attempt(string("/eval")).map(|_| CommandKind::Eval),
+        // Aliases people are used to from other bots.
+        attempt(string("/run")).map(|_| CommandKind::Eval),
+        attempt(string("/play")).map(|_| CommandKind::Eval),
+        attempt(string("/layout")).map(|_| CommandKind::Layout),
+    ));
     let bot_name = token('@').with(recognize(skip_many1(choice((alpha_num(), token('_'))))));
     let spaces1 = || (space(), spaces()).map(|_| ());
     let flag_name = recognize(skip_many1(alpha_num()));
-    let flag = (spaces1(), choice((string("--"), string("—"))), flag_name).map(|(_, _, name)| name);
-    let mut parser = string("/eval")
-        .with((
-            optional(bot_name),
-            many::<FlagsBuilder, _, _>(attempt(flag)),
-        ))
-        .skip(choice((spaces1(), eof())))
-        .and_then(|(bot_name, builder)| {
-            if builder.error {
-                Err(StringStreamError::UnexpectedParse)
+    let flag_value = || {
+        let quoted = between(
+            token('"'),
+            token('"'),
+            recognize(skip_many(satisfy(|c| c != '"'))),
+        );
+        let unquoted = recognize(skip_many1(satisfy(|c: char| !c.is_whitespace())));
+        choice((attempt(quoted), unquoted))
+    };
+    let flag = (spaces1(), choice((string("--"), string("—"))), flag_name)
+        .map(|(_, _, name)| name)
+        .then(move |name| {
+            if takes_value(name) {
+                // Value can be separated from the name via either `=` or spaces.
+                let separated_value = (choice((token('=').map(|_| ()), spaces1())), flag_value());
+                optional(attempt(separated_value))
+                    .map(move |value| (name, value.map(|(_, value)| value)))
+                    .left()
             } else {
-                Ok((bot_name, builder.flags))
+                value((name, None)).right()
             }
         });
-    parser
-        .parse(command)
-        .ok()
-        .map(|((bot_name, flags), content)| Command {
+    let mut parser = (
+        command_name,
+        optional(bot_name),
+        many::<FlagsBuilder<'_>, _, _>(attempt(flag)),
+    );
+    let ((kind, bot_name, builder), rest) =
+        parser.parse(command).map_err(|_| ParseError::NotCommand)?;
+    let invalid = |position: usize| {
+        let near = command[position..]
+            .split(char::is_whitespace)
+            .next()
+            .unwrap_or("");
+        let near = match near.char_indices().nth(MAX_NEAR_CHARS) {
+            Some((end, _)) => &near[..end],
+            None => near,
+        };
+        ParseError::Invalid {
             bot_name,
-            flags,
-            content,
-        })
+            position,
+            near,
+        }
+    };
+    if let Some(name) = builder.error {
+        // Include the dashes before the flag name.
+        let position = name.as_ptr() as usize - command.as_ptr() as usize;
+        let position = command[..position].trim_end_matches(['-', '—']).len();
+        return Err(invalid(position));
+    }
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        // Something like `/evaluate` is a different command.
+        if bot_name.is_none() && !builder.has_flags {
+            return Err(ParseError::NotCommand);
+        }
+        return Err(invalid(command.len() - rest.len()));
+    }
+    Ok(Command {
+        kind,
+        bot_name,
+        flags: builder.flags,
+        content: rest.trim_start(),
+    })
 }
 
+/// Maximum number of characters to show near a parse error.
+const MAX_NEAR_CHARS: usize = 20;
+
 pub fn get_help_message() -> String {
     let mut result = String::new();
     for info in FLAG_INFO.iter() {
-        writeln!(
-            result,
-            "<code>--{}</code> - {}",
-            info.name, info.description
-        )
+        match info.kind {
+            FlagKind::Switch(_) => write!(result, "<code>--{}</code>", info.name),
+            FlagKind::Value { placeholder, .. } => {
+                write!(result, "<code>--{} {}</code>", info.name, placeholder)
+            }
+        }
         .unwrap();
+        writeln!(result, " - {}", info.description).unwrap();
     }
+    result.push_str("\nUse <code>--bare --raw</code> to run the code exactly as written.\n");
     result
 }
 
 #[derive(Default)]
-struct FlagsBuilder {
+struct FlagsBuilder<'a> {
     flags: Flags,
-    error: bool,
+    has_flags: bool,
+    /// Name of the first invalid flag.
+    error: Option<&'a str>,
 }
 
-impl<'a> Extend<&'a str> for FlagsBuilder {
-    fn extend<T: IntoIterator<Item = &'a str>>(&mut self, iter: T) {
-        for name in iter {
-            match FLAG_INFO.iter().find(|info| info.name == name) {
-                Some(info) => (info.setter)(&mut self.flags),
-                None => self.error = true,
+impl<'a> Extend<(&'a str, Option<&'a str>)> for FlagsBuilder<'a> {
+    fn extend<T: IntoIterator<Item = (&'a str, Option<&'a str>)>>(&mut self, iter: T) {
+        for (name, value) in iter {
+            self.has_flags = true;
+            let info = FLAG_INFO.iter().find(|info| info.name == name);
+            let is_valid = match (info.map(|info| &info.kind), value) {
+                (Some(FlagKind::Switch(setter)), None) => {
+                    setter(&mut self.flags);
+                    true
+                }
+                (Some(FlagKind::Value { setter, .. }), Some(value)) => {
+                    setter(&mut self.flags, value)
+                }
+                _ => false,
+            };
+            // `--pretty` and `--display` are mutually exclusive.
+            let is_valid = is_valid && !(self.flags.pretty && self.flags.display);
+            if !is_valid && self.error.is_none() {
+                self.error = Some(name);
             }
         }
     }
 }
 
+fn takes_value(name: &str) -> bool {
+    FLAG_INFO
+        .iter()
+        .any(|info| info.name == name && matches!(info.kind, FlagKind::Value { .. }))
+}
+
 struct FlagInfo {
     name: &'static str,
     description: &'static str,
-    setter: fn(&mut Flags),
+    kind: FlagKind,
+}
+
+enum FlagKind {
+    /// Flag without value.
+    Switch(fn(&mut Flags)),
+    /// Flag with a value, whose setter returns false if the value is invalid.
+    Value {
+        placeholder: &'static str,
+        setter: fn(&mut Flags, &str) -> bool,
+    },
 }
 
 const FLAG_INFO: &[FlagInfo] = &[
     FlagInfo {
         name: "stable",
         description: "use stable channel",
-        setter: |flags| flags.channel = Some(Channel::Stable),
+        kind: FlagKind::Switch(|flags| flags.channel = Some(Channel::Stable)),
     },
     FlagInfo {
         name: "beta",
         description: "use beta channel",
-        setter: |flags| flags.channel = Some(Channel::Beta),
+        kind: FlagKind::Switch(|flags| flags.channel = Some(Channel::Beta)),
     },
     FlagInfo {
         name: "nightly",
         description: "use nightly channel",
-        setter: |flags| flags.channel = Some(Channel::Nightly),
+        kind: FlagKind::Switch(|flags| flags.channel = Some(Channel::Nightly)),
     },
     FlagInfo {
         name: "2015",
         description: "use 2015 edition",
-        setter: |flags| flags.edition = Some("2015"),
+        kind: FlagKind::Switch(|flags| flags.edition = Some("2015")),
     },
     FlagInfo {
         name: "2018",
         description: "use 2018 edition",
-        setter: |flags| flags.edition = Some("2018"),
+        kind: FlagKind::Switch(|flags| flags.edition = Some("2018")),
     },
     FlagInfo {
         name: "2021",
         description: "use 2021 edition",
-        setter: |flags| flags.edition = Some("2021"),
+        kind: FlagKind::Switch(|flags| flags.edition = Some("2021")),
     },
     FlagInfo {
         name: "2024",
         description: "use 2024 edition",
-        setter: |flags| flags.edition = Some("2024"),
+        kind: FlagKind::Switch(|flags| flags.edition = Some("2024")),
+    },
+    FlagInfo {
+        name: "edition",
+        description: "use the given edition",
+        kind: FlagKind::Value {
+            placeholder: "EDITION",
+            setter: |flags, value| match EDITIONS.iter().find(|e| **e == value) {
+                Some(edition) => {
+                    flags.edition = Some(edition);
+                    true
+                }
+                None => false,
+            },
+        },
     },
     FlagInfo {
         name: "debug",
         description: "do debug build",
-        setter: |flags| flags.mode = Some(Mode::Debug),
+        kind: FlagKind::Switch(|flags| flags.mode = Some(Mode::Debug)),
     },
     FlagInfo {
         name: "release",
         description: "do release build",
-        setter: |flags| flags.mode = Some(Mode::Release),
+        kind: FlagKind::Switch(|flags| flags.mode = Some(Mode::Release)),
     },
     FlagInfo {
         name: "bare",
-        description: "don't add any wrapping code",
-        setter: |flags| flags.bare = true,
+        description: "don't add any wrapping code, but still normalize characters",
+        kind: FlagKind::Switch(|flags| flags.bare = true),
+    },
+    FlagInfo {
+        name: "pretty",
+        description: "pretty-print the result",
+        kind: FlagKind::Switch(|flags| flags.pretty = true),
+    },
+    FlagInfo {
+        name: "display",
+        description: "print the result with Display instead of Debug, conflicts with --pretty",
+        kind: FlagKind::Switch(|flags| flags.display = true),
+    },
+    FlagInfo {
+        name: "both",
+        description: "show both stdout and stderr of the program",
+        kind: FlagKind::Switch(|flags| flags.both = true),
+    },
+    FlagInfo {
+        name: "file",
+        description: "send long output as a file in private chat",
+        kind: FlagKind::Switch(|flags| flags.file = true),
     },
     FlagInfo {
         name: "raw",
-        description: "don't convert any Unicode characters automatically",
-        setter: |flags| flags.raw = true,
+        description: "don't convert any Unicode characters, but still wrap the code",
+        kind: FlagKind::Switch(|flags| flags.raw = true),
+    },
+    FlagInfo {
+        name: "check",
+        description: "only check whether the code compiles without running it",
+        kind: FlagKind::Switch(|flags| flags.check = true),
+    },
+    FlagInfo {
+        name: "fmt",
+        description: "format the code with rustfmt instead of running it",
+        kind: FlagKind::Switch(|flags| flags.fmt = true),
+    },
+    FlagInfo {
+        name: "godbolt",
+        description: "show assembly from Compiler Explorer instead of running code",
+        kind: FlagKind::Switch(|flags| flags.godbolt = true),
+    },
+    FlagInfo {
+        name: "env",
+        description: "set environment variable before running the code",
+        kind: FlagKind::Value {
+            placeholder: "KEY=VALUE",
+            setter: |flags, value| match parse_env(value) {
+                Some(env) => {
+                    flags.env.push(env);
+                    true
+                }
+                None => false,
+            },
+        },
+    },
+    FlagInfo {
+        name: "dep",
+        description: "use the given crate available on the playground",
+        kind: FlagKind::Value {
+            placeholder: "CRATE[@VERSION]",
+            setter: |flags, value| match parse_dep(value) {
+                Some(dep) => {
+                    flags.deps.push(dep);
+                    true
+                }
+                None => false,
+            },
+        },
+    },
+    FlagInfo {
+        name: "expect",
+        description: "check whether the output matches the given text, which can be quoted",
+        kind: FlagKind::Value {
+            placeholder: "TEXT",
+            setter: |flags, value| {
+                flags.expect = Some(value.to_string());
+                true
+            },
+        },
+    },
+    FlagInfo {
+        name: "quiet",
+        description: "don't send the placeholder, so there is no feedback until the reply",
+        kind: FlagKind::Switch(|flags| flags.quiet = true),
+    },
+    FlagInfo {
+        name: "again",
+        description: "run the last command in this chat again",
+        kind: FlagKind::Switch(|flags| flags.again = true),
     },
     FlagInfo {
         name: "version",
         description: "show version instead of running code",
-        setter: |flags| flags.version = true,
+        kind: FlagKind::Switch(|flags| flags.version = true),
     },
     FlagInfo {
         name: "help",
         description: "show this help information",
-        setter: |flags| flags.help = true,
+        kind: FlagKind::Switch(|flags| flags.help = true),
     },
 ];
 
-#[derive(Debug, Default, Eq, PartialEq)]
+const EDITIONS: &[&str] = &["2015", "2018", "2021", "2024"];
+
+/// Parse environment variable in form of `KEY=VALUE`.
+fn parse_env(value: &str) -> Option<(String, String)> {
+    let (key, value) = value.split_once('=')?;
+    let mut chars = key.chars();
+    let is_valid_key = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    is_valid_key.then(|| (key.to_string(), value.to_string()))
+}
+
+/// Parse crate dependency in form of `CRATE` or `CRATE@VERSION`.
+fn parse_dep(value: &str) -> Option<(String, Option<String>)> {
+    let (name, version) = match value.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (value, None),
+    };
+    let is_valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-'));
+    let is_valid_version = version.is_none_or(|version| {
+        !version.is_empty() && version.chars().all(|c| c.is_ascii_digit() || c == '.')
+    });
+    (is_valid_name && is_valid_version).then(|| (name.to_string(), version.map(str::to_string)))
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Flags {
     pub channel: Option<Channel>,
     pub edition: Option<&'static str>,
     pub mode: Option<Mode>,
     pub bare: bool,
+    pub pretty: bool,
+    pub display: bool,
+    pub both: bool,
+    pub file: bool,
     pub raw: bool,
+    pub check: bool,
+    pub fmt: bool,
+    pub godbolt: bool,
+    pub env: Vec<(String, String)>,
+    pub deps: Vec<(String, Option<String>)>,
+    pub expect: Option<String>,
+    pub quiet: bool,
+    pub again: bool,
     pub version: bool,
     pub help: bool,
 }
@@ -166,7 +426,7 @@ pub enum Mode {
     Release,
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Channel {
     Stable,
@@ -184,20 +444,67 @@ impl Channel {
     }
 }
 
+/// Default number of commands listed by `/history`.
+const DEFAULT_HISTORY_COUNT: usize = 10;
+/// Maximum number of commands listed by `/history`.
+const MAX_HISTORY_COUNT: usize = 50;
+
+/// Parse `/history [N]` command, returns the number of commands to list.
+pub fn parse_history_command(text: &str, bot_name: &str) -> Option<usize> {
+    let (command, count) = match text.trim().split_once(char::is_whitespace) {
+        Some((command, count)) => (command, Some(count.trim())),
+        None => (text.trim(), None),
+    };
+    let (command, name) = match command.split_once('@') {
+        Some((command, name)) => (command, Some(name)),
+        None => (command, None),
+    };
+    if command != "/history" || name.is_some_and(|name| name != bot_name) {
+        return None;
+    }
+    match count {
+        Some(count) => count.parse().ok().map(|n: usize| n.min(MAX_HISTORY_COUNT)),
+        None => Some(DEFAULT_HISTORY_COUNT),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{parse_command, Channel, Command, Flags, Mode};
+    use super::{
+        get_help_message, parse_command, parse_history_command, Channel, Command, CommandKind,
+        Flags, Mode, ParseError,
+    };
+
+    #[test]
+    fn help_message() {
+        let help = get_help_message();
+        assert!(help.contains("<code>--raw</code> - don't convert any Unicode characters"));
+        assert!(help.contains("<code>--env KEY=VALUE</code> - "));
+        assert!(help.ends_with("<code>--bare --raw</code> to run the code exactly as written.\n"));
+    }
+
+    #[test]
+    fn history_command() {
+        assert_eq!(parse_history_command("/history", "bot"), Some(10));
+        assert_eq!(parse_history_command("/history@bot 3", "bot"), Some(3));
+        assert_eq!(parse_history_command("/history 1000", "bot"), Some(50));
+        assert_eq!(parse_history_command("/history@other", "bot"), None);
+        assert_eq!(parse_history_command("/history x", "bot"), None);
+        assert_eq!(parse_history_command("/historyx", "bot"), None);
+        assert_eq!(parse_history_command("/eval 1", "bot"), None);
+    }
 
     #[test]
     fn unknown_command() {
-        assert_eq!(parse_command("/unknown"), None);
+        assert_eq!(parse_command("/unknown"), Err(ParseError::NotCommand));
     }
 
     #[test]
     fn command_with_nothing() {
         assert_eq!(
             parse_command("/eval"),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: None,
                 flags: Flags::default(),
                 content: ""
@@ -209,7 +516,8 @@ mod tests {
     fn command_with_content() {
         assert_eq!(
             parse_command("/eval something after"),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: None,
                 flags: Flags::default(),
                 content: "something after"
@@ -221,7 +529,8 @@ mod tests {
     fn command_with_content_newline() {
         assert_eq!(
             parse_command("/eval\nsome content"),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: None,
                 flags: Flags::default(),
                 content: "some content"
@@ -231,7 +540,93 @@ mod tests {
 
     #[test]
     fn unknown_flag() {
-        assert_eq!(parse_command("/eval --unknown"), None);
+        assert!(parse_command("/eval --unknown").is_err());
+    }
+
+    #[test]
+    fn error_position() {
+        assert_eq!(
+            parse_command("/eval --unknown 1"),
+            Err(ParseError::Invalid {
+                bot_name: None,
+                position: 6,
+                near: "--unknown",
+            })
+        );
+        assert_eq!(
+            parse_command("/eval@bot --bare --env=1 x"),
+            Err(ParseError::Invalid {
+                bot_name: Some("bot"),
+                position: 17,
+                near: "--env=1",
+            })
+        );
+        assert_eq!(
+            parse_command("/eval --stable--2015"),
+            Err(ParseError::Invalid {
+                bot_name: None,
+                position: 14,
+                near: "--2015",
+            })
+        );
+        assert_eq!(parse_command("/evaluate 1"), Err(ParseError::NotCommand));
+    }
+
+    #[test]
+    fn env_flags() {
+        let command = parse_command("/eval --env RUST_LOG=debug --env=_A1=x=y --bare 1").unwrap();
+        assert_eq!(
+            command.flags.env,
+            [
+                ("RUST_LOG".to_string(), "debug".to_string()),
+                ("_A1".to_string(), "x=y".to_string()),
+            ]
+        );
+        assert!(command.flags.bare);
+        assert_eq!(command.content, "1");
+        assert!(parse_command("/eval --env 1").is_err());
+        assert!(parse_command("/eval --env 1A=x 1").is_err());
+        assert!(parse_command("/eval --env =x 1").is_err());
+        assert!(parse_command("/eval --env").is_err());
+        assert!(parse_command("/eval --bare=x 1").is_err());
+    }
+
+    #[test]
+    fn dep_flags() {
+        let command = parse_command("/eval --dep serde_json --dep=cfg-if@1.0 json!(1)").unwrap();
+        let expected_deps = [
+            ("serde_json".to_string(), None),
+            ("cfg-if".to_string(), Some("1.0".to_string())),
+        ];
+        assert_eq!(command.flags.deps, expected_deps);
+        assert_eq!(command.content, "json!(1)");
+        assert!(parse_command("/eval --dep rand@ 1").is_err());
+        assert!(parse_command("/eval --dep rand@^0.8 1").is_err());
+        assert!(parse_command("/eval --dep Serde 1").is_err());
+        assert!(parse_command("/eval --dep=a::b 1").is_err());
+        assert!(parse_command("/eval --dep").is_err());
+    }
+
+    #[test]
+    fn expect_flag() {
+        let command = parse_command("/eval --expect 3 1 + 2").unwrap();
+        assert_eq!(command.flags.expect.as_deref(), Some("3"));
+        assert_eq!(command.content, "1 + 2");
+        let command = parse_command("/eval --expect=\"[1, 2]\" vec![1, 2]").unwrap();
+        assert_eq!(command.flags.expect.as_deref(), Some("[1, 2]"));
+        assert_eq!(command.content, "vec![1, 2]");
+        let command = parse_command("/eval --expect \"\" ()").unwrap();
+        assert_eq!(command.flags.expect.as_deref(), Some(""));
+        assert_eq!(command.content, "()");
+    }
+
+    #[test]
+    fn conflicting_flags() {
+        assert!(parse_command("/eval --pretty --display 1").is_err());
+        assert!(parse_command("/eval --display --pretty 1").is_err());
+        let command = parse_command("/eval --display 1").unwrap();
+        assert!(command.flags.display);
+        assert!(!command.flags.pretty);
     }
 
     #[test]
@@ -248,7 +643,8 @@ mod tests {
             };
             assert_eq!(
                 parse_command(&format!("/eval --{}", name)),
-                Some(Command {
+                Ok(Command {
+                    kind: CommandKind::Eval,
                     bot_name: None,
                     flags: expected_flags,
                     content: ""
@@ -267,7 +663,8 @@ mod tests {
             };
             assert_eq!(
                 parse_command(&format!("/eval --{}", edition)),
-                Some(Command {
+                Ok(Command {
+                    kind: CommandKind::Eval,
                     bot_name: None,
                     flags: expected_flags,
                     content: ""
@@ -276,6 +673,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn edition_value_flag() {
+        let command = parse_command("/eval --edition 2018 1").unwrap();
+        assert_eq!(command.flags.edition, Some("2018"));
+        assert_eq!(command.content, "1");
+        let command = parse_command("/eval --edition=2024 --nightly").unwrap();
+        assert_eq!(command.flags.edition, Some("2024"));
+        assert_eq!(command.flags.channel, Some(Channel::Nightly));
+        // The last one wins, no matter in which form.
+        let command = parse_command("/eval --edition 2018 --2021").unwrap();
+        assert_eq!(command.flags.edition, Some("2021"));
+        assert!(parse_command("/eval --edition 2019 1").is_err());
+        assert!(parse_command("/eval --edition").is_err());
+        // Switch flags don't take values.
+        assert!(parse_command("/eval --2018=1").is_err());
+    }
+
     #[test]
     fn mode_flags() {
         const MODES: &[(&str, Mode)] = &[("debug", Mode::Debug), ("release", Mode::Release)];
@@ -286,7 +700,8 @@ mod tests {
             };
             assert_eq!(
                 parse_command(&format!("/eval --{}", name)),
-                Some(Command {
+                Ok(Command {
+                    kind: CommandKind::Eval,
                     bot_name: None,
                     flags: expected_flags,
                     content: ""
@@ -303,7 +718,8 @@ mod tests {
         };
         assert_eq!(
             parse_command("/eval --bare"),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: None,
                 flags: expected_flags,
                 content: ""
@@ -311,6 +727,76 @@ mod tests {
         );
     }
 
+    #[test]
+    fn check_flag() {
+        let command = parse_command("/eval --check --nightly --2024 let x = 1;").unwrap();
+        assert!(command.flags.check);
+        assert_eq!(command.flags.channel, Some(Channel::Nightly));
+        assert_eq!(command.flags.edition, Some("2024"));
+        assert_eq!(command.content, "let x = 1;");
+    }
+
+    #[test]
+    fn quiet_flag() {
+        let command = parse_command("/eval --quiet 1 + 1").unwrap();
+        assert!(command.flags.quiet);
+        assert_eq!(command.content, "1 + 1");
+    }
+
+    #[test]
+    fn again_flag() {
+        let expected_flags = Flags {
+            again: true,
+            ..Flags::default()
+        };
+        assert_eq!(
+            parse_command("/eval --again"),
+            Ok(Command {
+                kind: CommandKind::Eval,
+                bot_name: None,
+                flags: expected_flags,
+                content: ""
+            })
+        );
+        assert!(!parse_command("/eval --again")
+            .unwrap()
+            .has_extra_for_again());
+        assert!(!parse_command("/eval --again --quiet")
+            .unwrap()
+            .has_extra_for_again());
+        assert!(parse_command("/eval --again 1 + 1")
+            .unwrap()
+            .has_extra_for_again());
+        assert!(parse_command("/eval --again --release")
+            .unwrap()
+            .has_extra_for_again());
+    }
+
+    #[test]
+    fn fmt_flag() {
+        let command = parse_command("/eval --fmt --2018 fn  f(){}").unwrap();
+        assert!(command.flags.fmt);
+        assert_eq!(command.flags.edition, Some("2018"));
+        assert_eq!(command.content, "fn  f(){}");
+    }
+
+    #[test]
+    fn godbolt_flag() {
+        let expected_flags = Flags {
+            godbolt: true,
+            ..Flags::default()
+        };
+        assert_eq!(
+            parse_command("/eval --godbolt pub fn f() {}"),
+            Ok(Command {
+                kind: CommandKind::Eval,
+                bot_name: None,
+                flags: expected_flags,
+                content: "pub fn f() {}"
+            })
+        );
+    }
+
     #[test]
     fn version_flag() {
         let expected_flags = Flags {
@@ -319,7 +805,8 @@ mod tests {
         };
         assert_eq!(
             parse_command("/eval --version"),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: None,
                 flags: expected_flags,
                 content: ""
@@ -335,7 +822,8 @@ mod tests {
         };
         assert_eq!(
             parse_command("/eval --help"),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: None,
                 flags: expected_flags,
                 content: ""
@@ -345,7 +833,7 @@ mod tests {
 
     #[test]
     fn flags_without_sep() {
-        assert_eq!(parse_command("/eval --stable--2015"), None);
+        assert!(parse_command("/eval --stable--2015").is_err());
     }
 
     #[test]
@@ -356,13 +844,26 @@ mod tests {
             mode: Some(Mode::Debug),
             edition: Some("2015"),
             bare: true,
+            pretty: false,
+            display: false,
+            both: false,
+            file: false,
             raw: false,
+            check: false,
+            fmt: false,
+            godbolt: false,
+            env: vec![],
+            deps: vec![],
+            expect: None,
+            quiet: false,
+            again: false,
             version: true,
             help: false,
         };
         assert_eq!(
             parse_command(input),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: None,
                 flags: expected_flags,
                 content: "rest\ncontent"
@@ -374,7 +875,8 @@ mod tests {
     fn bot_name() {
         assert_eq!(
             parse_command("/eval@bot --bare content"),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: Some("bot"),
                 flags: Flags {
                     bare: true,
@@ -389,7 +891,8 @@ mod tests {
     fn flags_with_unicode_dash() {
         assert_eq!(
             parse_command("/eval@bot --bare —raw content"),
-            Some(Command {
+            Ok(Command {
+                kind: CommandKind::Eval,
                 bot_name: Some("bot"),
                 flags: Flags {
                     bare: true,
@@ -400,4 +903,51 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn eval_aliases() {
+        for alias in ["/run", "/play"] {
+            assert_eq!(
+                parse_command(&format!("{alias}@bot --nightly --bare content")),
+                Ok(Command {
+                    kind: CommandKind::Eval,
+                    bot_name: Some("bot"),
+                    flags: Flags {
+                        channel: Some(Channel::Nightly),
+                        bare: true,
+                        ..Flags::default()
+                    },
+                    content: "content",
+                })
+            );
+            assert_eq!(
+                parse_command(&format!("{alias} --release 1")),
+                Ok(Command {
+                    kind: CommandKind::Eval,
+                    bot_name: None,
+                    flags: Flags {
+                        mode: Some(Mode::Release),
+                        ..Flags::default()
+                    },
+                    content: "1",
+                })
+            );
+        }
+        assert_eq!(parse_command("/running 1"), Err(ParseError::NotCommand));
+        assert_eq!(parse_command("/played"), Err(ParseError::NotCommand));
+        assert_eq!(parse_command("/runs@bot 1"), Err(ParseError::NotCommand));
+    }
+
+    #[test]
+    fn layout_command() {
+        assert_eq!(
+            parse_command("/layout@bot vec![1u8]"),
+            Ok(Command {
+                kind: CommandKind::Layout,
+                bot_name: Some("bot"),
+                flags: Flags::default(),
+                content: "vec![1u8]",
+            })
+        );
+    }
 }
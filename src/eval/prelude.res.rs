@@ -1,8 +1,14 @@
+extern crate itertools;
 extern crate lazy_static;
 extern crate once_cell;
+extern crate rand;
+extern crate regex;
 
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use once_cell::sync::Lazy;
+use rand::prelude::*;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::any::{type_name, Any};
 use std::borrow::Cow;
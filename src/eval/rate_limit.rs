@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use telegram_types::bot::types::{Message, Time, UserId};
+
+/// Length of the window the limit applies to, in seconds.
+const PERIOD: u64 = 60;
+
+/// Per-user token bucket rate limiter.
+///
+/// Time is measured with the dates of the incoming messages, so each user
+/// can run at most `capacity` commands within any [`PERIOD`].
+/// A capacity of zero disables the limit.
+pub struct RateLimiter {
+    capacity: u32,
+    buckets: HashMap<UserId, Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: u64,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32) -> Self {
+        RateLimiter {
+            capacity,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Try to take a token for the given user.
+    ///
+    /// Returns the number of seconds to wait before next try if there is no token left.
+    pub fn check(&mut self, user: UserId, now: &Time) -> Result<(), u64> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(self.capacity);
+        let rate = capacity / PERIOD as f64;
+        let bucket = self.buckets.entry(user).or_insert(Bucket {
+            tokens: capacity,
+            last: now.0,
+        });
+        let elapsed = now.0.saturating_sub(bucket.last);
+        bucket.tokens = (bucket.tokens + elapsed as f64 * rate).min(capacity);
+        bucket.last = bucket.last.max(now.0);
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            Ok(())
+        } else {
+            Err(((1. - bucket.tokens) / rate).ceil() as u64)
+        }
+    }
+
+    /// Remove buckets which would have been refilled by the given date.
+    pub fn clear_old_buckets(&mut self, current_date: &Time) {
+        self.buckets
+            .retain(|_, bucket| current_date.0.saturating_sub(bucket.last) < PERIOD);
+    }
+}
+
+/// Get the time to check the limit at for the message, which is when it was last edited.
+pub fn message_time(message: &Message) -> &Time {
+    message.edit_date.as_ref().unwrap_or(&message.date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let mut limiter = RateLimiter::new(5);
+        let user = UserId(1);
+        for _ in 0..5 {
+            assert_eq!(limiter.check(user, &Time(1000)), Ok(()));
+        }
+        assert_eq!(limiter.check(user, &Time(1000)), Err(12));
+        // Other users are not affected.
+        assert_eq!(limiter.check(UserId(2), &Time(1000)), Ok(()));
+        // One token is refilled every 12 seconds.
+        assert_eq!(limiter.check(user, &Time(1006)), Err(6));
+        assert_eq!(limiter.check(user, &Time(1012)), Ok(()));
+        assert_eq!(limiter.check(user, &Time(1012)), Err(12));
+    }
+
+    #[test]
+    fn test_rate_limit_disabled() {
+        let mut limiter = RateLimiter::new(0);
+        for _ in 0..100 {
+            assert_eq!(limiter.check(UserId(1), &Time(1000)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_edits() {
+        let message = |edit_date: Option<u64>| -> Message {
+            serde_json::from_value(serde_json::json!({
+                "message_id": 1,
+                "date": 1000,
+                "edit_date": edit_date,
+                "chat": {"id": 1, "type": "private", "first_name": "a"},
+            }))
+            .unwrap()
+        };
+        let mut limiter = RateLimiter::new(1);
+        let user = UserId(1);
+        let mut check = |message: &Message| limiter.check(user, message_time(message));
+        assert_eq!(check(&message(None)), Ok(()));
+        // Edits take tokens at the time they are made.
+        assert_eq!(check(&message(Some(1030))), Err(30));
+        assert_eq!(check(&message(Some(1060))), Ok(()));
+        assert_eq!(check(&message(Some(1061))), Err(59));
+    }
+
+    #[test]
+    fn test_clear_old_buckets() {
+        let mut limiter = RateLimiter::new(5);
+        limiter.check(UserId(1), &Time(1000)).unwrap();
+        limiter.check(UserId(2), &Time(1030)).unwrap();
+        limiter.clear_old_buckets(&Time(1070));
+        assert!(!limiter.buckets.contains_key(&UserId(1)));
+        assert!(limiter.buckets.contains_key(&UserId(2)));
+    }
+}
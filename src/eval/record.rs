@@ -1,44 +1,132 @@
 use log::error;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use std::io;
-use telegram_types::bot::types::{MessageId, Time};
+use std::path::PathBuf;
+use telegram_types::bot::types::{ChatId, MessageId, Time, UserId};
 
 const RECORD_LIST_FILE: &str = "record_list.json";
+/// Maximum number of characters of command excerpt kept in records.
+const MAX_EXCERPT_CHARS: usize = 40;
 
-pub struct RecordService(VecDeque<Record>);
+pub struct RecordService {
+    path: PathBuf,
+    list: VecDeque<Record>,
+    /// Position of the latest record of each message, offset by `evicted`.
+    index: HashMap<MessageId, usize>,
+    /// Position of the record holding the last command of each chat, offset by `evicted`.
+    commands: HashMap<ChatId, usize>,
+    /// Number of records which have been removed from the front of the list.
+    evicted: usize,
+    max_len: usize,
+}
 
 impl RecordService {
     /// Create record list, restore from record list file if possible.
-    pub fn init() -> Self {
-        match File::open(RECORD_LIST_FILE) {
+    ///
+    /// At most `max_len` records are kept, older records get evicted first.
+    pub fn init(max_len: usize) -> Self {
+        Self::load(PathBuf::from(RECORD_LIST_FILE), max_len)
+    }
+
+    fn load(path: PathBuf, max_len: usize) -> Self {
+        let list: Vec<Record> = match File::open(&path) {
             Ok(file) => match serde_json::from_reader(file) {
-                Ok(list) => return RecordService(list),
-                Err(e) => error!("failed to parse record list: {:?}", e),
+                Ok(list) => list,
+                Err(e) => {
+                    error!("failed to parse record list: {:?}", e);
+                    Default::default()
+                }
             },
             Err(e) => {
                 // It's fine that the file doesn't exist.
                 if e.kind() != io::ErrorKind::NotFound {
                     error!("failed to read record list: {:?}", e);
                 }
+                Default::default()
             }
+        };
+        let mut result = RecordService {
+            path,
+            list: Default::default(),
+            index: Default::default(),
+            commands: Default::default(),
+            evicted: 0,
+            max_len,
+        };
+        for record in list {
+            result.push(record);
         }
-        RecordService(Default::default())
+        result
     }
 
     /// Push a new record with reply being empty.
     pub fn push_record(&mut self, msg: MessageId, date: Time) {
         let reply = None;
-        self.0.push_back(Record { msg, reply, date });
+        let from = None;
+        let excerpt = None;
+        let command = None;
+        let file_sent = false;
+        let generation = 0;
+        self.push(Record {
+            msg,
+            reply,
+            date,
+            from,
+            excerpt,
+            command,
+            file_sent,
+            generation,
+        });
+    }
+
+    fn push(&mut self, record: Record) {
+        if self.list.len() >= self.max_len {
+            self.pop_front();
+        }
+        let position = self.evicted + self.list.len();
+        self.index.insert(record.msg, position);
+        if let Some(command) = &record.command {
+            self.track_command(command.chat, position);
+        }
+        self.list.push_back(record);
+    }
+
+    /// Record the position of the last command of the chat,
+    /// and drop the command text of the previous one.
+    fn track_command(&mut self, chat: ChatId, position: usize) {
+        let previous = self.commands.insert(chat, position);
+        if let Some(previous) = previous.filter(|&p| p != position) {
+            if let Some(r) = self.list.get_mut(previous - self.evicted) {
+                r.command = None;
+            }
+        }
+    }
+
+    fn pop_front(&mut self) -> Option<Record> {
+        let record = self.list.pop_front()?;
+        // Only remove from the index if there isn't a newer record of the same message.
+        if self.index.get(&record.msg) == Some(&self.evicted) {
+            self.index.remove(&record.msg);
+        }
+        if let Some(command) = &record.command {
+            if self.commands.get(&command.chat) == Some(&self.evicted) {
+                self.commands.remove(&command.chat);
+            }
+        }
+        self.evicted += 1;
+        Some(record)
     }
 
     fn find_record(&self, msg: MessageId) -> Option<&Record> {
-        self.0.iter().rev().find(|r| r.msg == msg)
+        let position = *self.index.get(&msg)?;
+        self.list.get(position - self.evicted)
     }
 
     fn find_record_mut(&mut self, msg: MessageId) -> Option<&mut Record> {
-        self.0.iter_mut().rev().find(|r| r.msg == msg)
+        let position = *self.index.get(&msg)?;
+        self.list.get_mut(position - self.evicted)
     }
 
     /// Find the reply message of the given record.
@@ -60,24 +148,86 @@ impl RecordService {
         }
     }
 
+    /// Mark the output file of the given record as sent.
+    ///
+    /// Returns whether it has not been sent before.
+    pub fn mark_file_sent(&mut self, msg: MessageId) -> bool {
+        match self.find_record_mut(msg) {
+            Some(r) => !std::mem::replace(&mut r.file_sent, true),
+            None => false,
+        }
+    }
+
+    /// Set the sender of the given record, and keep an excerpt of the command text.
+    pub fn set_sender(&mut self, msg: MessageId, from: UserId, text: &str) {
+        if let Some(r) = self.find_record_mut(msg) {
+            r.from = Some(from);
+            r.excerpt = Some(make_excerpt(text));
+        }
+    }
+
+    /// List the latest `count` commands sent by the given user, newest first.
+    ///
+    /// Each item contains the message, its reply, and the excerpt of the command.
+    pub fn history(&self, user: UserId, count: usize) -> Vec<(MessageId, Option<MessageId>, &str)> {
+        self.list
+            .iter()
+            .rev()
+            .filter(|r| r.from == Some(user))
+            .filter_map(|r| Some((r.msg, r.reply, r.excerpt.as_deref()?)))
+            .take(count)
+            .collect()
+    }
+
+    /// Set the command text of the given record as the last command of the chat.
+    pub fn set_last_command(&mut self, msg: MessageId, chat: ChatId, text: String) {
+        let position = match self.index.get(&msg) {
+            Some(&position) => position,
+            None => return,
+        };
+        self.list[position - self.evicted].command = Some(LastCommand { chat, text });
+        self.track_command(chat, position);
+    }
+
+    /// Find the text of the last command in the given chat.
+    pub fn last_command(&self, chat: ChatId) -> Option<&str> {
+        let position = *self.commands.get(&chat)?;
+        let record = self.list.get(position - self.evicted)?;
+        record.command.as_ref().map(|c| c.text.as_str())
+    }
+
+    /// Bump the generation of the given record, and return the new generation.
+    pub fn bump_generation(&mut self, msg: MessageId) -> Option<u64> {
+        self.find_record_mut(msg).map(|r| {
+            r.generation += 1;
+            r.generation
+        })
+    }
+
+    /// Whether the given generation is still the latest of the given record.
+    pub fn is_current_generation(&self, msg: MessageId, generation: u64) -> bool {
+        self.find_record(msg)
+            .is_some_and(|r| r.generation == generation)
+    }
+
     /// Clear records order than 48hrs before the given date.
     pub fn clear_old_records(&mut self, current_date: &Time) {
         // We can clean up records up to 48hrs ago, because messages before that
         // cannot be edited anymore.
         let date_to_clean = current_date.0 - 48 * 3600;
-        while let Some(record) = self.0.pop_front() {
-            if record.date.0 > date_to_clean {
-                self.0.push_front(record);
-                break;
-            }
+        while self
+            .list
+            .front()
+            .is_some_and(|record| record.date.0 <= date_to_clean)
+        {
+            self.pop_front();
         }
     }
-}
 
-impl Drop for RecordService {
-    fn drop(&mut self) {
-        match File::create(RECORD_LIST_FILE) {
-            Ok(file) => match serde_json::to_writer(file, &self.0) {
+    /// Write the record list to the record list file.
+    pub fn flush(&self) {
+        match File::create(&self.path) {
+            Ok(file) => match serde_json::to_writer(file, &self.list) {
                 Ok(()) => {}
                 Err(e) => error!("failed to serialize record list: {:?}", e),
             },
@@ -86,9 +236,202 @@ impl Drop for RecordService {
     }
 }
 
+impl Drop for RecordService {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 struct Record {
     msg: MessageId,
     reply: Option<MessageId>,
     date: Time,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from: Option<UserId>,
+    /// First line of the command text, truncated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    excerpt: Option<String>,
+    /// Only kept for the last command of each chat, for `--again`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command: Option<LastCommand>,
+    /// Whether the output has been sent as a file, which is only done once for each message.
+    #[serde(default, skip_serializing_if = "is_false")]
+    file_sent: bool,
+    /// Bumped on each edit of the message, so that results of superseded edits can be dropped.
+    #[serde(skip)]
+    generation: u64,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn make_excerpt(text: &str) -> String {
+    let line = text.trim().lines().next().unwrap_or("");
+    let mut excerpt: String = line.chars().take(MAX_EXCERPT_CHARS).collect();
+    if excerpt.len() < text.trim().len() {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
+#[derive(Deserialize, Serialize)]
+struct LastCommand {
+    chat: ChatId,
+    text: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_flush_and_reload() {
+        let path = env::temp_dir().join(format!("record_list_{}.json", std::process::id()));
+        let mut records = RecordService::load(path.clone(), 100);
+        records.push_record(MessageId(1), Time(1000));
+        records.push_record(MessageId(2), Time(2000));
+        records.set_reply(MessageId(2), MessageId(3));
+        records.flush();
+
+        let restored = RecordService::load(path.clone(), 100);
+        assert_eq!(restored.list.len(), 2);
+        assert_eq!(restored.find_reply(MessageId(1)), None);
+        assert_eq!(restored.find_reply(MessageId(2)), Some(MessageId(3)));
+
+        drop(records);
+        drop(restored);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_sent() {
+        let path = env::temp_dir().join(format!("record_list_file_{}.json", std::process::id()));
+        let mut records = RecordService::load(path.clone(), 100);
+        records.push_record(MessageId(1), Time(1000));
+        assert!(records.mark_file_sent(MessageId(1)));
+        assert!(!records.mark_file_sent(MessageId(1)));
+        assert!(!records.mark_file_sent(MessageId(2)));
+        records.flush();
+
+        let mut restored = RecordService::load(path.clone(), 100);
+        assert!(!restored.mark_file_sent(MessageId(1)));
+
+        drop(records);
+        drop(restored);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_generation() {
+        let path = env::temp_dir().join(format!("record_list_gen_{}.json", std::process::id()));
+        let mut records = RecordService::load(path.clone(), 100);
+        records.push_record(MessageId(1), Time(1000));
+        assert!(records.is_current_generation(MessageId(1), 0));
+        assert_eq!(records.bump_generation(MessageId(1)), Some(1));
+        assert_eq!(records.bump_generation(MessageId(1)), Some(2));
+        assert!(!records.is_current_generation(MessageId(1), 1));
+        assert!(records.is_current_generation(MessageId(1), 2));
+        assert_eq!(records.bump_generation(MessageId(2)), None);
+        assert!(!records.is_current_generation(MessageId(2), 0));
+
+        drop(records);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_last_command() {
+        let path = env::temp_dir().join(format!("record_list_cmd_{}.json", std::process::id()));
+        let mut records = RecordService::load(path.clone(), 100);
+        assert_eq!(records.last_command(ChatId(1)), None);
+        records.push_record(MessageId(1), Time(1000));
+        records.set_last_command(MessageId(1), ChatId(1), "/eval 1".to_string());
+        records.push_record(MessageId(2), Time(2000));
+        records.set_last_command(MessageId(2), ChatId(2), "/eval 2".to_string());
+        records.push_record(MessageId(3), Time(3000));
+        records.set_last_command(MessageId(3), ChatId(1), "/eval 3".to_string());
+        assert_eq!(records.last_command(ChatId(1)), Some("/eval 3"));
+        assert_eq!(records.last_command(ChatId(2)), Some("/eval 2"));
+        // Only the last command of each chat is kept.
+        assert!(records.list[0].command.is_none());
+        records.flush();
+
+        let mut restored = RecordService::load(path.clone(), 100);
+        assert_eq!(restored.last_command(ChatId(1)), Some("/eval 3"));
+        assert_eq!(restored.last_command(ChatId(2)), Some("/eval 2"));
+        restored.clear_old_records(&Time(2000 + 48 * 3600));
+        assert_eq!(restored.last_command(ChatId(1)), Some("/eval 3"));
+        assert_eq!(restored.last_command(ChatId(2)), None);
+
+        drop(records);
+        drop(restored);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_history() {
+        let path = env::temp_dir().join(format!("record_list_hist_{}.json", std::process::id()));
+        let mut records = RecordService::load(path.clone(), 100);
+        records.push_record(MessageId(1), Time(1000));
+        records.set_sender(MessageId(1), UserId(1), "/eval 1\n2");
+        records.set_reply(MessageId(1), MessageId(11));
+        records.push_record(MessageId(2), Time(2000));
+        records.set_sender(MessageId(2), UserId(2), "/eval 2");
+        records.push_record(MessageId(3), Time(3000));
+        let long = format!("/eval {}", "x".repeat(100));
+        records.set_sender(MessageId(3), UserId(1), &long);
+        let history = records.history(UserId(1), 10);
+        let excerpt = format!("/eval {}…", "x".repeat(34));
+        assert_eq!(
+            history,
+            [
+                (MessageId(3), None, excerpt.as_str()),
+                (MessageId(1), Some(MessageId(11)), "/eval 1…"),
+            ]
+        );
+        assert_eq!(records.history(UserId(1), 1).len(), 1);
+        assert_eq!(records.history(UserId(3), 10), []);
+
+        drop(records);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_eviction() {
+        let path = env::temp_dir().join(format!("record_list_evict_{}.json", std::process::id()));
+        let mut records = RecordService::load(path.clone(), 3);
+        for i in 1..=4 {
+            records.push_record(MessageId(i), Time(i as u64 * 1000));
+            records.set_reply(MessageId(i), MessageId(i + 100));
+        }
+        // The oldest record is evicted.
+        assert_eq!(records.list.len(), 3);
+        assert_eq!(records.find_reply(MessageId(1)), None);
+        assert_eq!(records.find_reply(MessageId(2)), Some(MessageId(102)));
+        assert_eq!(records.find_reply(MessageId(4)), Some(MessageId(104)));
+        assert_eq!(records.bump_generation(MessageId(1)), None);
+
+        // Evicting an older record doesn't affect a newer one of the same message.
+        records.push_record(MessageId(2), Time(5000));
+        records.push_record(MessageId(5), Time(6000));
+        assert_eq!(records.find_reply(MessageId(2)), None);
+        records.set_reply(MessageId(2), MessageId(200));
+        records.push_record(MessageId(6), Time(7000));
+        assert_eq!(records.find_reply(MessageId(2)), Some(MessageId(200)));
+        assert_eq!(records.find_reply(MessageId(3)), None);
+        assert_eq!(records.find_reply(MessageId(4)), None);
+
+        records.clear_old_records(&Time(6000 + 48 * 3600));
+        assert_eq!(records.list.len(), 1);
+        assert_eq!(records.find_reply(MessageId(2)), None);
+        assert_eq!(records.find_reply(MessageId(6)), None);
+        records.set_reply(MessageId(6), MessageId(300));
+        assert_eq!(records.find_reply(MessageId(6)), Some(MessageId(300)));
+
+        drop(records);
+        fs::remove_file(&path).unwrap();
+    }
 }
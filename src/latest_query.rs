@@ -0,0 +1,95 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use telegram_types::bot::inline_mode::InlineQueryId;
+use telegram_types::bot::types::UserId;
+
+/// Latest inline query of each user which hasn't been answered.
+///
+/// Queries of a user may be handled concurrently when they type quickly,
+/// and answers to older queries shouldn't override the newer one.
+#[derive(Default)]
+pub struct LatestQueries(Mutex<HashMap<UserId, InlineQueryId>>);
+
+impl LatestQueries {
+    /// Record the query as the latest one of the user.
+    ///
+    /// The query is forgotten once the returned guard is dropped, whether or not it's answered,
+    /// and older queries wouldn't be answered afterwards.
+    pub fn observe(&self, user: UserId, query: &InlineQueryId) -> LatestQuery<'_> {
+        self.0.lock().insert(user, query.clone());
+        LatestQuery {
+            queries: self,
+            user,
+            query: query.clone(),
+        }
+    }
+}
+
+/// Guard of a query being handled, see [`LatestQueries::observe`].
+pub struct LatestQuery<'a> {
+    queries: &'a LatestQueries,
+    user: UserId,
+    query: InlineQueryId,
+}
+
+impl LatestQuery<'_> {
+    /// Whether the query is still the latest one of the user, so that it should be answered.
+    pub fn should_answer(&self) -> bool {
+        self.queries.0.lock().get(&self.user) == Some(&self.query)
+    }
+}
+
+impl Drop for LatestQuery<'_> {
+    fn drop(&mut self) {
+        let mut queries = self.queries.0.lock();
+        if queries.get(&self.user) == Some(&self.query) {
+            queries.remove(&self.user);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_answer() {
+        let queries = LatestQueries::default();
+        let id = |s: &str| InlineQueryId(s.to_string());
+        let a = queries.observe(UserId(1), &id("a"));
+        let b = queries.observe(UserId(1), &id("b"));
+        let c = queries.observe(UserId(2), &id("c"));
+        // Newer query is answered before the older one.
+        assert!(b.should_answer());
+        drop(b);
+        assert!(!a.should_answer());
+        drop(a);
+        // Older query finishes first.
+        let d = queries.observe(UserId(1), &id("d"));
+        let e = queries.observe(UserId(1), &id("e"));
+        assert!(!d.should_answer());
+        drop(d);
+        assert!(e.should_answer());
+        drop(e);
+        // Other users are not affected.
+        assert!(c.should_answer());
+        drop(c);
+        assert!(queries.0.lock().is_empty());
+    }
+
+    #[test]
+    fn test_unanswered_query() {
+        let queries = LatestQueries::default();
+        let id = |s: &str| InlineQueryId(s.to_string());
+        // The query fails or times out before being answered.
+        drop(queries.observe(UserId(1), &id("a")));
+        assert!(queries.0.lock().is_empty());
+        // Dropping an older query doesn't forget the newer one.
+        let b = queries.observe(UserId(1), &id("b"));
+        let c = queries.observe(UserId(1), &id("c"));
+        drop(b);
+        assert!(c.should_answer());
+        drop(c);
+        assert!(queries.0.lock().is_empty());
+    }
+}
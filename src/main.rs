@@ -4,13 +4,18 @@ mod bot;
 mod bot_runner;
 mod cratesio;
 mod eval;
+mod latest_query;
+mod messages;
+mod mute;
 mod rustdoc;
 mod shutdown;
 #[cfg(unix)]
 mod signal;
 mod task_tracker;
+mod update_log;
 mod upgrade;
 mod utils;
+mod webhook;
 
 use crate::bot::{Bot, Error};
 use crate::bot_runner::BotRunner;
@@ -28,6 +33,7 @@ use std::env;
 use std::fmt::Write as FmtWrite;
 use std::future::Future;
 use std::io::Write as IOWrite;
+use std::time::Instant;
 use telegram_types::bot::types::{ChatId, UserId};
 use tokio::runtime::Runtime;
 
@@ -37,6 +43,7 @@ static ADMIN_ID: Lazy<UserId> = Lazy::new(|| {
         .and_then(|s| str::parse(&s).map(UserId).ok())
         .expect("BOT_ADMIN_ID must be a valid user id")
 });
+static START_TIME: Lazy<Instant> = Lazy::new(Instant::now);
 static ABOUT_MESSAGE: Lazy<String> = Lazy::new(|| {
     format!(
         "{} {}\n{}",
@@ -50,18 +57,25 @@ fn main() {
     // We don't care if we fail to load .env file.
     let _ = dotenv::from_path(std::env::current_dir().unwrap().join(".env"));
     init_logger();
+    Lazy::force(&START_TIME);
 
     let shutdown = Shutdown::create();
     #[cfg(unix)]
     signal::init(shutdown.clone());
     upgrade::init(shutdown.clone());
-    rustdoc::init();
+    // Loading the index takes a while, so skip it if the bot wouldn't start.
+    if bot_runner::is_enabled("rustdoc") {
+        rustdoc::init();
+    }
 
     info!("Running as `{}`", env!("USER_AGENT"));
 
     let runtime = Runtime::new().unwrap();
     let (spawner, waiter) = task_tracker::create(&runtime);
     let client = build_client();
+    if let Some(config) = &*webhook::CONFIG {
+        spawner.spawn(webhook::serve(config, spawner.clone(), shutdown.register()));
+    }
     let bot_runner = BotRunner {
         client: &client,
         spawner: &spawner,
@@ -71,10 +85,12 @@ fn main() {
 
     // Kick off eval bot.
     let client_clone = client.clone();
+    let spawner_clone = spawner.clone();
+    let shutdown_clone = shutdown.clone();
     let eval_receiver = bot_runner.run(
         "eval",
         "EVAL_TELEGRAM_TOKEN",
-        move |bot| EvalBot::new(client_clone, bot),
+        move |bot| EvalBot::new(client_clone, bot, &spawner_clone, &shutdown_clone),
         EvalBot::handle_update,
     );
 
@@ -118,6 +134,8 @@ fn main() {
         }
         let (_, first_bot) = bots.into_iter().next().expect("no bot configured?");
         send_message_to_admin(&first_bot, start_msg).await.unwrap();
+        let bot = first_bot.clone();
+        spawner.set_panic_reporter(move |message| report_panic_to_admin(&bot, &message));
         first_bot
     });
 
@@ -160,8 +178,16 @@ fn init_logger() {
                     }
                 }
             };
+            // Tag the line with the update being handled to correlate concurrent requests.
+            let write_update_id = match update_log::current() {
+                Some(id) => write!(buf, "{id}> "),
+                None => Ok(()),
+            };
             let write_args = writeln!(buf, "{}", record.args());
-            write_header.and(write_module_path).and(write_args)
+            write_header
+                .and(write_module_path)
+                .and(write_update_id)
+                .and(write_args)
         })
         .init();
 }
@@ -186,6 +212,12 @@ fn report_error_to_admin(bot: &Bot, error: &Error) {
     tokio::spawn(send_message_to_admin(bot, message));
 }
 
+fn report_panic_to_admin(bot: &Bot, message: &str) {
+    use htmlescape::encode_minimal;
+    let message = format!("task panicked:\n<pre>{}</pre>", encode_minimal(message));
+    tokio::spawn(send_message_to_admin(bot, message));
+}
+
 fn send_message_to_admin(bot: &Bot, msg: String) -> impl Future<Output = Result<(), ()>> {
     let chat_id = ChatId(ADMIN_ID.0);
     bot.send_message(chat_id, msg)
@@ -0,0 +1,171 @@
+use log::warn;
+use once_cell::sync::Lazy;
+use std::env;
+use std::fmt::{self, Write as _};
+
+/// Catalog of user-facing messages.
+///
+/// Messages with `{name}` placeholders are templates to be filled via [`fill`].
+pub struct Messages {
+    pub processing: &'static str,
+    pub processing_elapsed: &'static str,
+    pub updating: &'static str,
+    pub no_output: &'static str,
+    pub no_trailing_newline: &'static str,
+    pub nothing: &'static str,
+    pub output_sent_as_file: &'static str,
+    pub compiles_cleanly: &'static str,
+    pub compile_error: &'static str,
+    pub runtime_error: &'static str,
+    pub killed: &'static str,
+    pub killed_memory: &'static str,
+    pub rate_limited: &'static str,
+    pub muted: &'static str,
+    pub unmuted: &'static str,
+    pub no_last_command: &'static str,
+    pub history_empty: &'static str,
+    pub history_private_only: &'static str,
+    pub layout_usage: &'static str,
+    pub whatis_not_found: &'static str,
+    pub error_parse: &'static str,
+    pub error_code_too_large: &'static str,
+    pub error_layout_bare: &'static str,
+    pub error_env_unwrapped: &'static str,
+    pub error_again_extra: &'static str,
+    pub error_unknown_crate: &'static str,
+    pub error_crate_version: &'static str,
+    pub error_builder: &'static str,
+    pub error_request: &'static str,
+    pub error_timeout: &'static str,
+    pub error_eval_timeout: &'static str,
+    pub error_status_code: &'static str,
+    pub error_unknown: &'static str,
+    pub error_run: &'static str,
+}
+
+static EN: Messages = Messages {
+    processing: "Processing...",
+    processing_elapsed: "Processing... ({secs}s)",
+    updating: "Updating...",
+    no_output: "(no output)",
+    no_trailing_newline: "(no trailing newline)",
+    nothing: "(nothing??)",
+    output_sent_as_file: "(output is sent as a file)",
+    compiles_cleanly: "compiles cleanly ✓",
+    compile_error: "⚠ compile error: {error}",
+    runtime_error: "💥 runtime error: {error}",
+    killed: "killed: exceeded the time or memory limit of the playground",
+    killed_memory: "killed: exceeded memory limit",
+    rate_limited: "rate limited, try again in {wait}s",
+    muted: "muted in this chat, use /unmute to unmute",
+    unmuted: "unmuted in this chat",
+    no_last_command: "no previous command to run again in this chat",
+    history_empty: "no recent commands",
+    history_private_only: "/history is only available in private chat",
+    layout_usage: "usage: /layout EXPR, e.g. /layout Some(1u8)",
+    whatis_not_found: "no item found",
+    error_parse: "error: couldn't parse command near '{near}'",
+    error_code_too_large: "error: code too large ({len} KB > {max} KB limit)",
+    error_layout_bare: "error: /layout can't be used with --bare",
+    error_env_unwrapped: "error: --env can't be used with --bare or your own fn main",
+    error_again_extra: "error: --again can't be used with code or flags other than --quiet",
+    error_unknown_crate: "error: crate not available on the playground: \
+        <code>{name}</code>, see <a href=\"{url}\">available crates</a>",
+    error_crate_version: "error: crate version not available on the playground: \
+        <code>{name}@{version}</code>, {available} is available",
+    error_builder: "error: builder error",
+    error_request: "error: failed to request",
+    error_timeout: "error: timeout",
+    error_eval_timeout: "error: evaluation timed out after {secs}s",
+    error_status_code: "error: status code: {status}",
+    error_unknown: "error: unknown error",
+    error_run: "error: failed to run code",
+};
+
+/// List of supported locales, the first one is the fallback.
+static LOCALES: &[(&str, &Messages)] = &[("en", &EN)];
+
+/// Messages for the locale specified via `BOT_LOCALE`.
+pub static MESSAGES: Lazy<&'static Messages> = Lazy::new(|| match env::var("BOT_LOCALE") {
+    Ok(locale) => Messages::for_locale(&locale),
+    Err(_) => LOCALES[0].1,
+});
+
+impl Messages {
+    /// Get the messages for the given locale, e.g. `en` or `en_US.UTF-8`.
+    pub fn for_locale(locale: &str) -> &'static Messages {
+        let language = locale.split(['_', '-', '.']).next().unwrap_or("");
+        match LOCALES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(language))
+        {
+            Some((_, messages)) => messages,
+            None => {
+                let (fallback, messages) = LOCALES[0];
+                warn!("unsupported locale {}, fallback to {}", locale, fallback);
+                messages
+            }
+        }
+    }
+}
+
+/// Fill the `{name}` placeholders in the template with the given values.
+///
+/// Unknown placeholders are kept as is, and values are not scanned for placeholders.
+pub fn fill(template: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            let (_, value) = args.iter().find(|(n, _)| *n == name)?;
+            Some((end, value))
+        });
+        match value {
+            Some((end, value)) => {
+                write!(result, "{value}").unwrap();
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_for_locale() {
+        assert!(ptr::eq(Messages::for_locale("en"), &EN));
+        assert!(ptr::eq(Messages::for_locale("en_US.UTF-8"), &EN));
+        assert!(ptr::eq(Messages::for_locale("EN-gb"), &EN));
+        // Unknown locale falls back to English.
+        assert!(ptr::eq(Messages::for_locale("xx"), &EN));
+        assert!(ptr::eq(Messages::for_locale(""), &EN));
+        assert_eq!(Messages::for_locale("xx").no_output, "(no output)");
+    }
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(
+            fill(EN.rate_limited, &[("wait", &12)]),
+            "rate limited, try again in 12s"
+        );
+        assert_eq!(
+            fill(EN.error_parse, &[("near", &"{near}")]),
+            "error: couldn't parse command near '{near}'"
+        );
+        assert_eq!(fill("{a}{b}{a}", &[("a", &1), ("b", &"x")]), "1x1");
+        assert_eq!(fill("{x} {} {", &[("a", &1)]), "{x} {} {");
+        assert_eq!(fill("é{a}é", &[("a", &"啊")]), "é啊é");
+    }
+}
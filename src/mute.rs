@@ -0,0 +1,165 @@
+use crate::bot::Bot;
+use crate::messages::MESSAGES;
+use log::{debug, error, warn};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+use telegram_types::bot::types::{ChatId, ChatMemberStatus, Message};
+use tokio::sync::Mutex;
+
+/// Set of chats where the bot is muted.
+pub struct MuteService {
+    path: PathBuf,
+    chats: HashSet<ChatId>,
+}
+
+impl MuteService {
+    /// Create mute list, restore from the given mute list file if possible.
+    pub fn init(file: &str) -> Self {
+        Self::load(PathBuf::from(file))
+    }
+
+    fn load(path: PathBuf) -> Self {
+        match File::open(&path) {
+            Ok(file) => match serde_json::from_reader(file) {
+                Ok(chats) => return MuteService { path, chats },
+                Err(e) => error!("failed to parse mute list: {:?}", e),
+            },
+            Err(e) => {
+                // It's fine that the file doesn't exist.
+                if e.kind() != io::ErrorKind::NotFound {
+                    error!("failed to read mute list: {:?}", e);
+                }
+            }
+        }
+        MuteService {
+            path,
+            chats: Default::default(),
+        }
+    }
+
+    /// Whether the bot is muted in the given chat.
+    pub fn is_muted(&self, chat: ChatId) -> bool {
+        self.chats.contains(&chat)
+    }
+
+    /// Mute or unmute the given chat, and write the change to the mute list file.
+    pub fn set_muted(&mut self, chat: ChatId, muted: bool) {
+        let changed = if muted {
+            self.chats.insert(chat)
+        } else {
+            self.chats.remove(&chat)
+        };
+        if changed {
+            self.flush();
+        }
+    }
+
+    fn flush(&self) {
+        match File::create(&self.path) {
+            Ok(file) => match serde_json::to_writer(file, &self.chats) {
+                Ok(()) => {}
+                Err(e) => error!("failed to serialize mute list: {:?}", e),
+            },
+            Err(e) => error!("failed to create mute list: {:?}", e),
+        }
+    }
+}
+
+/// Handle `/mute` and `/unmute` commands from chat admins.
+///
+/// Returns whether the message is such a command.
+pub async fn may_handle_command(bot: &Bot, mutes: &Mutex<MuteService>, message: &Message) -> bool {
+    let text = match &message.text {
+        Some(text) => text,
+        None => return false,
+    };
+    let muted = match parse_command(text, bot.username) {
+        Some(muted) => muted,
+        None => return false,
+    };
+    let from = match &message.from {
+        Some(from) => from,
+        None => return true,
+    };
+    let chat_id = message.chat.id;
+    let is_admin = from.id == *crate::ADMIN_ID || {
+        let request = bot.get_chat_member(chat_id, from.id);
+        match request.execute().await {
+            Ok(member) => matches!(
+                member.status,
+                ChatMemberStatus::Creator | ChatMemberStatus::Administrator
+            ),
+            Err(err) => {
+                warn!("error getting chat member: {:?}", err);
+                false
+            }
+        }
+    };
+    if !is_admin {
+        debug!("ignoring mute command from non-admin");
+        return true;
+    }
+    mutes.lock().await.set_muted(chat_id, muted);
+    let text = if muted {
+        MESSAGES.muted
+    } else {
+        MESSAGES.unmuted
+    };
+    if let Err(err) = bot.send_message(chat_id, text).execute().await {
+        warn!("error sending: {:?}", err);
+    }
+    true
+}
+
+/// Parse `/mute` or `/unmute` command, returns whether to mute.
+pub fn parse_command(text: &str, bot_name: &str) -> Option<bool> {
+    let (command, name) = match text.trim().split_once('@') {
+        Some((command, name)) => (command, Some(name)),
+        None => (text.trim(), None),
+    };
+    if name.is_some_and(|name| name != bot_name) {
+        return None;
+    }
+    match command {
+        "/mute" => Some(true),
+        "/unmute" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_mute_and_reload() {
+        let path = env::temp_dir().join(format!("mute_list_{}.json", std::process::id()));
+        let mut mutes = MuteService::load(path.clone());
+        mutes.set_muted(ChatId(1), true);
+        mutes.set_muted(ChatId(2), true);
+        mutes.set_muted(ChatId(2), false);
+        assert!(mutes.is_muted(ChatId(1)));
+        assert!(!mutes.is_muted(ChatId(2)));
+        assert!(!mutes.is_muted(ChatId(3)));
+
+        let restored = MuteService::load(path.clone());
+        assert!(restored.is_muted(ChatId(1)));
+        assert!(!restored.is_muted(ChatId(2)));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("/mute", "bot"), Some(true));
+        assert_eq!(parse_command(" /unmute\n", "bot"), Some(false));
+        assert_eq!(parse_command("/mute@bot", "bot"), Some(true));
+        assert_eq!(parse_command("/mute@other", "bot"), None);
+        assert_eq!(parse_command("/mute now", "bot"), None);
+        assert_eq!(parse_command("/eval 1", "bot"), None);
+    }
+}
@@ -1,6 +1,7 @@
 use fst_subseq_ascii_caseless::SubseqAsciiCaseless;
 use once_cell::sync::Lazy;
 use rustdoc_seeker::{DocItem, RustDoc, RustDocSeeker, TypeItem};
+use std::env;
 use std::fs;
 use std::ops::Deref;
 
@@ -16,7 +17,7 @@ static SEEKER: Lazy<RustDocSeeker> = Lazy::new(|| {
                 || item
                     .parent
                     .as_ref()
-                    .map_or(false, |p| p.as_ref().contains(SPECIAL_CHARS))
+                    .is_some_and(|p| p.as_ref().contains(SPECIAL_CHARS))
                 || item.name.as_ref().contains(SPECIAL_CHARS)
             {
                 panic!("Found path with HTML special character: {item:?}");
@@ -26,11 +27,47 @@ static SEEKER: Lazy<RustDocSeeker> = Lazy::new(|| {
     doc.build()
 });
 
+/// Base URL of the documentation, which should match the `search-index.js`.
+static BASE_URL: Lazy<String> = Lazy::new(|| {
+    let version = env::var("RUSTDOC_VERSION").ok();
+    build_base_url(version.as_deref())
+});
+
+fn build_base_url(version: Option<&str>) -> String {
+    match version {
+        Some(version) if !version.is_empty() => {
+            assert!(
+                !version.contains(['/', '?', '#']),
+                "invalid RUSTDOC_VERSION: {version}"
+            );
+            format!("https://doc.rust-lang.org/{version}/")
+        }
+        _ => "https://doc.rust-lang.org/".to_string(),
+    }
+}
+
 pub fn init() {
     Lazy::force(&SEEKER);
+    Lazy::force(&BASE_URL);
+}
+
+pub fn base_url() -> &'static str {
+    &BASE_URL
 }
 
+/// Search items matching the given path.
 pub fn query(path: &str) -> Vec<&'static DocItem> {
+    search(&SEEKER, path, true)
+}
+
+/// Search items matching only the name in the given path.
+///
+/// This is useful as suggestions when [`query`] finds nothing.
+pub fn suggest(path: &str) -> Vec<&'static DocItem> {
+    search(&SEEKER, path, false)
+}
+
+fn search<'a>(seeker: &'a RustDocSeeker, path: &str, match_path: bool) -> Vec<&'a DocItem> {
     let path = path
         .split("::")
         .map(|s| s.trim_matches(char::is_whitespace))
@@ -41,9 +78,9 @@ pub fn query(path: &str) -> Vec<&'static DocItem> {
         None => return vec![],
     };
     let lowercase_name = name.to_ascii_lowercase();
-    let mut matched_items = SEEKER
+    let mut matched_items = seeker
         .search(&SubseqAsciiCaseless::new(&lowercase_name))
-        .filter(|item| matches_path(item, root, path))
+        .filter(|item| !match_path || matches_path(item, root, path))
         .collect::<Vec<_>>();
     if matched_items.is_empty() {
         return vec![];
@@ -122,7 +159,7 @@ pub enum ItemType {
     Other,
 }
 
-impl<'a> From<&'a TypeItem> for ItemType {
+impl From<&TypeItem> for ItemType {
     fn from(item: &TypeItem) -> Self {
         match item {
             TypeItem::Keyword(_) => ItemType::Keyword,
@@ -160,8 +197,23 @@ fn matches_path(item: &DocItem, root: RootLevel, path: &[&str]) -> bool {
 mod test {
     use super::*;
     use rustdoc_seeker::DocItem;
+    use std::collections::BTreeSet;
     use string_cache::DefaultAtom as Atom;
 
+    #[test]
+    fn test_build_base_url() {
+        assert_eq!(build_base_url(None), "https://doc.rust-lang.org/");
+        assert_eq!(build_base_url(Some("")), "https://doc.rust-lang.org/");
+        assert_eq!(
+            build_base_url(Some("1.80.0")),
+            "https://doc.rust-lang.org/1.80.0/"
+        );
+        assert_eq!(
+            build_base_url(Some("nightly")),
+            "https://doc.rust-lang.org/nightly/"
+        );
+    }
+
     #[test]
     fn test_matches_path() {
         let item = DocItem::new(
@@ -181,4 +233,29 @@ mod test {
         assert!(!matches_path(&item, RootLevel::Std, &["x"]));
         assert!(!matches_path(&item, RootLevel::Alloc, &["BTreeMap"]));
     }
+
+    #[test]
+    fn test_suggest_without_path() {
+        let items = [
+            DocItem::new(
+                TypeItem::Struct(Atom::from("HashMap")),
+                None,
+                Atom::from("std::collections"),
+                Atom::from(""),
+            ),
+            DocItem::new(
+                TypeItem::Struct(Atom::from("BTreeMap")),
+                None,
+                Atom::from("std::collections"),
+                Atom::from(""),
+            ),
+        ];
+        let seeker = RustDoc::new(items.into_iter().collect::<BTreeSet<_>>()).build();
+        assert!(search(&seeker, "sync::HashMap", true).is_empty());
+        let suggestions = search(&seeker, "sync::HashMap", false);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].name.as_ref(), "HashMap");
+        let suggestions = search(&seeker, "core::Map", false);
+        assert_eq!(suggestions.len(), 2);
+    }
 }
@@ -1,5 +1,10 @@
-use log::debug;
+use futures::FutureExt as _;
+use log::{debug, error};
+use once_cell::sync::OnceCell;
+use std::any::Any;
 use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::runtime::{Handle, Runtime};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
@@ -7,35 +12,63 @@ use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 pub fn create(runtime: &Runtime) -> (Arc<TaskSpawner>, TaskWaiter) {
     let (sender, receiver) = mpsc::unbounded_channel();
     let handle = runtime.handle().clone();
+    let active_count = Arc::new(AtomicUsize::new(0));
     (
-        Arc::new(TaskSpawner { handle, sender }),
-        TaskWaiter { receiver },
+        Arc::new(TaskSpawner {
+            handle,
+            sender,
+            active_count: active_count.clone(),
+            panic_reporter: Default::default(),
+        }),
+        TaskWaiter {
+            receiver,
+            active_count,
+        },
     )
 }
 
-enum TaskState {
-    Starting,
-    Ended,
-}
+type PanicReporter = Box<dyn Fn(String) + Send + Sync>;
 
 pub struct TaskSpawner {
     handle: Handle,
-    sender: UnboundedSender<TaskState>,
+    /// Notifies the waiter when a task ends.
+    sender: UnboundedSender<()>,
+    active_count: Arc<AtomicUsize>,
+    /// Called with the panic message when a task panics.
+    panic_reporter: Arc<OnceCell<PanicReporter>>,
 }
 
 impl TaskSpawner {
+    /// Number of tasks which have been spawned but not yet finished.
+    pub fn active_count(&self) -> usize {
+        self.active_count.load(Ordering::SeqCst)
+    }
+
+    /// Set the function to report panics of tasks, which can only be set once.
+    pub fn set_panic_reporter(&self, reporter: impl Fn(String) + Send + Sync + 'static) {
+        if self.panic_reporter.set(Box::new(reporter)).is_err() {
+            panic!("panic reporter is already set");
+        }
+    }
+
     pub fn spawn<F>(&self, future: F)
     where
         F: Future<Output = ()> + Send + 'static,
     {
         let sender = self.sender.clone();
-        match sender.send(TaskState::Starting) {
-            Ok(()) => {}
-            Err(_) => unreachable!("waiter has been dropped"),
-        }
+        let active_count = self.active_count.clone();
+        let panic_reporter = self.panic_reporter.clone();
+        active_count.fetch_add(1, Ordering::SeqCst);
         self.handle.spawn(async move {
-            future.await;
-            match sender.send(TaskState::Ended) {
+            if let Err(payload) = AssertUnwindSafe(future).catch_unwind().await {
+                let message = panic_message(&*payload);
+                error!("task panicked: {}", message);
+                if let Some(report) = panic_reporter.get() {
+                    report(message);
+                }
+            }
+            active_count.fetch_sub(1, Ordering::SeqCst);
+            match sender.send(()) {
                 Ok(()) => {}
                 Err(_) => unreachable!("waiter is dropped before task finishes"),
             }
@@ -43,25 +76,56 @@ impl TaskSpawner {
     }
 }
 
+/// Extract the message from the payload of a panic.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
 pub struct TaskWaiter {
-    receiver: UnboundedReceiver<TaskState>,
+    receiver: UnboundedReceiver<()>,
+    active_count: Arc<AtomicUsize>,
 }
 
 impl TaskWaiter {
     pub async fn wait(mut self) {
-        let mut counter = 0usize;
         loop {
             match self.receiver.recv().await {
-                Some(TaskState::Starting) => counter += 1,
-                Some(TaskState::Ended) => {
-                    counter -= 1;
-                    if counter == 0 {
+                Some(()) => {
+                    let count = self.active_count.load(Ordering::SeqCst);
+                    if count == 0 {
                         debug!("all tasks done");
                         break;
                     }
+                    debug!("{} tasks remaining", count);
                 }
-                None => unreachable!("remaining {} unfinished tasks", counter),
+                None => unreachable!("spawner is dropped before tasks finish"),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    #[test]
+    fn test_panicking_task() {
+        let runtime = Runtime::new().unwrap();
+        let (spawner, waiter) = create(&runtime);
+        let reported = Arc::new(Mutex::new(vec![]));
+        let reported_clone = reported.clone();
+        spawner.set_panic_reporter(move |message| reported_clone.lock().push(message));
+        spawner.spawn(async { panic!("oops {}", 42) });
+        spawner.spawn(async {});
+        runtime.block_on(waiter.wait());
+        assert_eq!(spawner.active_count(), 0);
+        assert_eq!(*reported.lock(), ["oops 42"]);
+    }
+}
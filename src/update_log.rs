@@ -0,0 +1,42 @@
+use std::future::Future;
+use telegram_types::bot::types::UpdateId;
+
+tokio::task_local! {
+    /// Id of the update being handled.
+    static CURRENT_UPDATE: i64;
+}
+
+/// Run the future with the given update id attached to its log lines.
+pub fn scope<F: Future>(id: UpdateId, future: F) -> impl Future<Output = F::Output> {
+    CURRENT_UPDATE.scope(id.0, future)
+}
+
+/// Run the function with the given update id attached to its log lines.
+pub fn sync_scope<R>(id: UpdateId, f: impl FnOnce() -> R) -> R {
+    CURRENT_UPDATE.sync_scope(id.0, f)
+}
+
+/// Id of the update being handled by the current task, if any.
+pub fn current() -> Option<i64> {
+    CURRENT_UPDATE.try_with(|id| *id).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current() {
+        assert_eq!(current(), None);
+        assert_eq!(sync_scope(UpdateId(42), current), Some(42));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let id = runtime.block_on(scope(UpdateId(7), async {
+            tokio::task::yield_now().await;
+            current()
+        }));
+        assert_eq!(id, Some(7));
+        assert_eq!(current(), None);
+    }
+}
@@ -1,10 +1,13 @@
 use crate::shutdown::Shutdown;
 use log::{debug, info};
 use notify::{self, Event, EventKind, RecommendedWatcher, RecursiveMode, Result, Watcher};
+use std::fs::OpenOptions;
+use std::io;
 use std::path::Path;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
+use std::time::SystemTime;
 
 const NOTIFY_FILE: &str = "upgrade";
 
@@ -16,6 +19,12 @@ pub fn init(shutdown: Arc<Shutdown>) {
     });
 }
 
+/// Touch the notify file as if an upgrade happens.
+pub fn trigger() -> io::Result<()> {
+    let file = OpenOptions::new().append(true).open(NOTIFY_FILE)?;
+    file.set_modified(SystemTime::now())
+}
+
 fn init_watcher(tx: Sender<Result<Event>>) -> Result<impl Watcher> {
     let mut watcher = RecommendedWatcher::new(tx, Default::default())?;
     watcher.watch(Path::new(NOTIFY_FILE), RecursiveMode::NonRecursive)?;
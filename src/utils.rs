@@ -1,20 +1,29 @@
 use htmlescape::encode_minimal;
 use phf::phf_map;
 use std::borrow::Cow;
-use std::fmt;
+use std::fmt::Write as _;
+use std::time::Duration;
 use telegram_types::bot::types::{ChatType, Message};
 use unicode_width::UnicodeWidthChar;
 
-#[derive(Clone, Copy, Debug)]
-pub enum Void {}
+/// Maximum length of a Telegram message, in UTF-16 code units.
+pub const MAX_MESSAGE_LEN: usize = 4096;
 
-impl fmt::Display for Void {
-    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Ok(())
-    }
+/// Truncate the output to the given number of lines and columns,
+/// as well as to at most `max_len` UTF-16 code units including the trailing `...`.
+pub fn truncate_output(
+    output: &str,
+    max_lines: usize,
+    max_total_columns: usize,
+    max_len: usize,
+) -> Cow<'_, str> {
+    truncate_to_len(
+        truncate_lines(output, max_lines, max_total_columns),
+        max_len,
+    )
 }
 
-pub fn truncate_output(output: &str, max_lines: usize, max_total_columns: usize) -> Cow<'_, str> {
+fn truncate_lines(output: &str, max_lines: usize, max_total_columns: usize) -> Cow<'_, str> {
     let mut line_count = 0;
     let mut column_count = 0;
     for (pos, c) in output.char_indices() {
@@ -31,6 +40,10 @@ pub fn truncate_output(output: &str, max_lines: usize, max_total_columns: usize)
         if c == '\n' {
             line_count += 1;
             if line_count == max_lines {
+                // Nothing is lost if the output just ends with a newline.
+                if pos + 1 == output.len() {
+                    break;
+                }
                 return format!("{}...", &output[..pos]).into();
             }
         }
@@ -38,6 +51,23 @@ pub fn truncate_output(output: &str, max_lines: usize, max_total_columns: usize)
     output.into()
 }
 
+fn truncate_to_len(output: Cow<'_, str>, max_len: usize) -> Cow<'_, str> {
+    // Position where the content needs to end to leave space for `...`.
+    let mut end = None;
+    let mut len = 0;
+    for (pos, c) in output.char_indices() {
+        len += c.len_utf16();
+        if end.is_none() && len + 3 > max_len {
+            end = Some(pos);
+        }
+        if len > max_len {
+            let end = end.unwrap();
+            return format!("{}...", &output[..end]).into();
+        }
+    }
+    output
+}
+
 pub fn is_message_from_private_chat(message: &Message) -> bool {
     matches!(message.chat.kind, ChatType::Private { .. })
 }
@@ -56,38 +86,238 @@ pub fn encode_with_code(output: &mut String, text: &str) {
     }
 }
 
+/// Format the number in a compact human-friendly form like `890k` or `12.3M`.
+pub fn format_count(n: u64) -> String {
+    const UNITS: [char; 3] = ['k', 'M', 'B'];
+    if n < 1000 {
+        return n.to_string();
+    }
+    let n = u128::from(n);
+    let mut base = 1;
+    for (i, unit) in UNITS.into_iter().enumerate() {
+        base *= 1000;
+        // Keep one decimal place for values below 100.
+        let tenths = (n * 10 + base / 2) / base;
+        if tenths < 1000 {
+            return match tenths % 10 {
+                0 => format!("{}{unit}", tenths / 10),
+                decimal => format!("{}.{decimal}{unit}", tenths / 10),
+            };
+        }
+        // Rounding may carry into the next unit.
+        let whole = (n + base / 2) / base;
+        if whole < 1000 || i == UNITS.len() - 1 {
+            return format!("{whole}{unit}");
+        }
+    }
+    unreachable!()
+}
+
+/// Format the duration in a compact form like `1d 2h 3m 4s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, mins, secs) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    let mut result = String::new();
+    for (value, unit) in [(days, 'd'), (hours, 'h'), (mins, 'm')] {
+        if value > 0 || !result.is_empty() {
+            write!(result, "{value}{unit} ").unwrap();
+        }
+    }
+    write!(result, "{secs}s").unwrap();
+    result
+}
+
 static UNICODE_CHARS_MAP: phf::Map<char, &str> = phf_map! {
     '“' => "\"",
     '”' => "\"",
     '‘' => "\'",
     '’' => "\'",
     '—' => "--",
+    '–' => "-",
     '\u{a0}' => " ",
+    '\u{3000}' => " ",
+    '…' => "...",
+    '\u{2212}' => "-",
 };
 
 /// Normalize the mistakenly inputted Unicode character to the corresponding ASCII character.
 ///
 /// For the table what characters this function will convert, you can refer to
-/// [`UNICODE_CHARS_MAP`].
+/// [`UNICODE_CHARS_MAP`]. Fullwidth forms of ASCII characters, which CJK input methods
+/// produce, are converted as well. Characters inside string and char literals are kept as is,
+/// except for invisible characters, which are removed everywhere.
 ///
 /// Time complexity of this is `O(n)`.
-pub fn normalize_unicode_chars(input: &str) -> Cow<str> {
+pub fn normalize_unicode_chars(input: &str) -> Cow<'_, str> {
     // If the input is ASCII, there is no need to normalize.
     if input.is_ascii() {
         return input.into();
     }
 
     let mut output = String::with_capacity(input.len());
+    scan_code(input, |span, text| match span {
+        CodeSpan::Code | CodeSpan::Comment => push_normalized(&mut output, text),
+        CodeSpan::Literal => output.extend(text.chars().filter(|c| !is_invisible_char(*c))),
+    });
+    output.into()
+}
+
+/// Remove comments and content of string and char literals from the code.
+///
+/// This is useful for checking the existence of some code pattern.
+pub fn strip_comments_and_literals(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    scan_code(input, |span, text| match span {
+        CodeSpan::Code => output.push_str(text),
+        CodeSpan::Comment => output.push(' '),
+        CodeSpan::Literal => output.push_str("\"\""),
+    });
+    output
+}
+
+enum CodeSpan {
+    Code,
+    Comment,
+    Literal,
+}
+
+/// Split the code into spans of code, comments, and string or char literals.
+fn scan_code<'a>(input: &'a str, mut f: impl FnMut(CodeSpan, &'a str)) {
+    let mut pos = 0;
+    let mut code_start = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        let prev = input[..pos].chars().next_back();
+        let (span, len) = if let Some(len) = comment_len(rest) {
+            (CodeSpan::Comment, len)
+        } else if let Some(len) = literal_len(rest, prev) {
+            (CodeSpan::Literal, len)
+        } else {
+            pos += rest.chars().next().unwrap().len_utf8();
+            continue;
+        };
+        if code_start < pos {
+            f(CodeSpan::Code, &input[code_start..pos]);
+        }
+        f(span, &rest[..len]);
+        pos += len;
+        code_start = pos;
+    }
+    if code_start < input.len() {
+        f(CodeSpan::Code, &input[code_start..]);
+    }
+}
+
+/// Convert fullwidth form of an ASCII character, e.g. `（`, to the ASCII character.
+fn fullwidth_to_ascii(c: char) -> Option<char> {
+    match c {
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0),
+        _ => None,
+    }
+}
 
+/// Whether the character is a zero-width character or BOM, which users can't see.
+fn is_invisible_char(c: char) -> bool {
+    matches!(c, '\u{feff}' | '\u{200b}'..='\u{200d}' | '\u{2060}')
+}
+
+fn push_normalized(output: &mut String, input: &str) {
     for c in input.chars() {
-        if let Some(replacement) = UNICODE_CHARS_MAP.get(&c) {
+        if is_invisible_char(c) {
+            continue;
+        } else if let Some(replacement) = UNICODE_CHARS_MAP.get(&c) {
             output.push_str(replacement);
+        } else if let Some(c) = fullwidth_to_ascii(c) {
+            output.push(c);
         } else {
             output.push(c);
         }
     }
+}
 
-    output.into()
+/// Get the length of comment at the beginning of the input, if any.
+fn comment_len(input: &str) -> Option<usize> {
+    if input.starts_with("//") {
+        return Some(input.find('\n').unwrap_or(input.len()));
+    }
+    if !input.starts_with("/*") {
+        return None;
+    }
+    // Block comments can be nested.
+    let mut depth = 0;
+    let mut pos = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        if rest.starts_with("/*") {
+            depth += 1;
+            pos += 2;
+        } else if rest.starts_with("*/") {
+            depth -= 1;
+            pos += 2;
+            if depth == 0 {
+                return Some(pos);
+            }
+        } else {
+            pos += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    Some(input.len())
+}
+
+/// Get the length of string or char literal at the beginning of the input, if any.
+///
+/// Unterminated literal extends to the end of the input.
+fn literal_len(input: &str, prev: Option<char>) -> Option<usize> {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+    match input.chars().next()? {
+        '"' => Some(string_len(input)),
+        '\'' => char_len(input),
+        'r' | 'b' | 'c' if !prev.is_some_and(is_ident_char) => {
+            let raw = input
+                .strip_prefix('r')
+                .or_else(|| input.strip_prefix("br"))
+                .or_else(|| input.strip_prefix("cr"))?;
+            let hashes = raw.len() - raw.trim_start_matches('#').len();
+            let content = raw[hashes..].strip_prefix('"')?;
+            let start = input.len() - content.len();
+            let terminator = format!("\"{}", "#".repeat(hashes));
+            Some(match content.find(&terminator) {
+                Some(end) => start + end + terminator.len(),
+                None => input.len(),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn string_len(input: &str) -> usize {
+    let mut chars = input.char_indices().skip(1);
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return pos + 1,
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+fn char_len(input: &str) -> Option<usize> {
+    let mut chars = input.char_indices().skip(1);
+    match chars.next()? {
+        (_, '\\') => {
+            chars.next();
+            let end = chars.find(|&(_, c)| c == '\'').map(|(pos, _)| pos + 1);
+            Some(end.unwrap_or(input.len()))
+        }
+        // Otherwise it may be a lifetime or label.
+        _ => match chars.next()? {
+            (pos, '\'') => Some(pos + 1),
+            _ => None,
+        },
+    }
 }
 
 #[cfg(test)]
@@ -134,15 +364,73 @@ mod test {
                 input: &[("a\n", 10)],
                 expected: &[("a\n", 2), ("a...", 1)],
             },
+            Testcase {
+                input: &[("a\n", 3)],
+                expected: &[("a\n", 3)],
+            },
+            Testcase {
+                input: &[("abc", 1)],
+                expected: &[("abc", 1)],
+            },
+            Testcase {
+                input: &[("啊\n", 3), ("啊", 1)],
+                expected: &[("啊\n", 2), ("啊...", 1)],
+            },
         ];
         for Testcase { input, expected } in TESTCASES.iter() {
             assert_eq!(
-                truncate_output(&construct_string(input), MAX_LINES, MAX_TOTAL_COLUMNS),
+                truncate_output(
+                    &construct_string(input),
+                    MAX_LINES,
+                    MAX_TOTAL_COLUMNS,
+                    MAX_MESSAGE_LEN
+                ),
                 construct_string(expected)
             );
         }
     }
 
+    #[test]
+    fn test_truncate_output_len() {
+        let truncate =
+            |output: &str, max_len| truncate_output(output, 10, 1000, max_len).into_owned();
+        assert_eq!(truncate("abcde", 5), "abcde");
+        assert_eq!(truncate("abcdef", 5), "ab...");
+        // Multi-byte characters are counted in UTF-16 code units.
+        assert_eq!(truncate("啊啊啊啊啊", 5), "啊啊啊啊啊");
+        assert_eq!(truncate("啊啊啊啊啊啊", 5), "啊啊...");
+        assert_eq!(truncate("🦀🦀🦀", 6), "🦀🦀🦀");
+        assert_eq!(truncate("🦀🦀🦀", 5), "🦀...");
+        assert_eq!(truncate("a🦀🦀", 5), "a🦀🦀");
+        assert_eq!(truncate("ab🦀🦀", 5), "ab...");
+        // Both limits are applied.
+        assert_eq!(truncate_output("a\nb\nc", 2, 1000, 4), "a...");
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1_000), "1k");
+        assert_eq!(format_count(1_250), "1.3k");
+        assert_eq!(format_count(99_960), "100k");
+        assert_eq!(format_count(890_123), "890k");
+        assert_eq!(format_count(999_499), "999k");
+        assert_eq!(format_count(999_500), "1M");
+        assert_eq!(format_count(1_234_567), "1.2M");
+        assert_eq!(format_count(12_345_678), "12.3M");
+        assert_eq!(format_count(4_500_000_000), "4.5B");
+        assert_eq!(format_count(u64::MAX), "18446744074B");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(3600)), "1h 0m 0s");
+        assert_eq!(format_duration(Duration::from_secs(90061)), "1d 1h 1m 1s");
+    }
+
     #[test]
     fn test_normalize_unicode_chars() {
         const TEST_MAP: &[(&str, &str)] = &[
@@ -151,10 +439,76 @@ mod test {
             ("‘a’", "'a'"),
             ("--eval", "--eval"),
             ("--—", "----"),
+            ("let\u{a0}a = 1;", "let a = 1;"),
+            ("(1..=3).map(|_| …)", "(1..=3).map(|_| ...)"),
+            ("\u{2212}1", "-1"),
+            ("foo（）", "foo()"),
+            ("let a = 1；", "let a = 1;"),
+            ("std：：mem", "std::mem"),
+            ("０１２３４５６７８９", "0123456789"),
+            ("1 – 2", "1 - 2"),
+            ("ｆｏｏ［０］．ｂａｒ｛｝，＜＞！＝＆", "foo[0].bar{},<>!=&"),
+            ("！～", "!~"),
+            ("let\u{3000}a", "let a"),
+            (
+                "\u{feff}let\u{200b} a\u{200c} =\u{200d} 1\u{2060};",
+                "let a = 1;",
+            ),
+            // Characters without mapping are kept as is.
+            (
+                "let a = １； // 中文注释\nprintln!(\"你好，世界\")",
+                "let a = 1; // 中文注释\nprintln!(\"你好，世界\")",
+            ),
         ];
 
         TEST_MAP.iter().for_each(|(input, expected)| {
             assert_eq!(&normalize_unicode_chars(input), expected);
         });
     }
+
+    #[test]
+    fn test_strip_comments_and_literals() {
+        const TEST_MAP: &[(&str, &str)] = &[
+            ("\"fn main()\".len()", "\"\".len()"),
+            ("1 // println!(\"{}\", 1)\n+ 1", "1  \n+ 1"),
+            ("/* fn main() */ fn main() {}", "  fn main() {}"),
+            ("['a', r#\"\"#, b\"\\\"\"]", "[\"\", \"\", b\"\"]"),
+            ("fn f<'a>() {}", "fn f<'a>() {}"),
+        ];
+
+        TEST_MAP.iter().for_each(|(input, expected)| {
+            assert_eq!(&strip_comments_and_literals(input), expected, "{input}");
+        });
+    }
+
+    #[test]
+    fn test_normalize_unicode_chars_in_literals() {
+        const TEST_MAP: &[(&str, &str)] = &[
+            ("println!(“a”, \"“b”\")", "println!(\"a\", \"“b”\")"),
+            (r#""\"“" + ‘x’"#, r#""\"“" + 'x'"#),
+            (r#""\\" + “x”"#, r#""\\" + "x""#),
+            (
+                "['“', '\\'', '\\u{201c}'] —",
+                "['“', '\\'', '\\u{201c}'] --",
+            ),
+            ("fn f<'a>(x: &'a str) —", "fn f<'a>(x: &'a str) --"),
+            (r##"r"“\" —"##, r##"r"“\" --"##),
+            (r###"r#"“" "“"# —"###, r###"r#"“" "“"# --"###),
+            (r###"br##"“"#“"## —"###, r###"br##"“"#“"## --"###),
+            // Raw identifier and identifiers ending with r are not raw strings.
+            ("r#type — for_r“", "r#type -- for_r\""),
+            // Quotes in comments don't start literals.
+            ("// don't \"“\n“", "// don't \"\"\n\""),
+            ("/* /* \" */ “ */ “", "/* /* \" */ \" */ \""),
+            // Unterminated literals extend to the end.
+            ("\"“", "\"“"),
+            ("r#\"“\"", "r#\"“\""),
+            // Invisible characters are removed even in literals.
+            ("\"a\u{200b}b\" —", "\"ab\" --"),
+        ];
+
+        TEST_MAP.iter().for_each(|(input, expected)| {
+            assert_eq!(&normalize_unicode_chars(input), expected, "{input}");
+        });
+    }
 }
@@ -0,0 +1,193 @@
+use crate::task_tracker::TaskSpawner;
+use futures::channel::oneshot::Receiver;
+use futures::future::{self, Either, FutureExt as _};
+use futures::pin_mut;
+use http_body_util::{BodyExt as _, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use telegram_types::bot::types::Update;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:8443";
+const SECRET_TOKEN_HEADER: &str = "x-telegram-bot-api-secret-token";
+
+/// Webhook config, `None` if bots should use long polling instead.
+pub static CONFIG: Lazy<Option<Config>> = Lazy::new(|| {
+    let url = env::var("WEBHOOK_URL").ok()?;
+    let listen = env::var("WEBHOOK_LISTEN").map_or(DEFAULT_LISTEN_ADDR.parse().unwrap(), |s| {
+        s.parse()
+            .expect("WEBHOOK_LISTEN must be a valid socket address")
+    });
+    Some(Config {
+        url: url.trim_end_matches('/').to_string(),
+        listen,
+    })
+});
+
+/// Routes to each bot, keyed by the path of its webhook.
+static ROUTES: Lazy<Mutex<HashMap<String, Route>>> = Lazy::new(Default::default);
+
+struct Route {
+    /// Secret token which Telegram is expected to send back in requests.
+    secret_token: String,
+    sender: UnboundedSender<Update>,
+}
+
+pub struct Config {
+    /// Public URL which the webhook paths are appended to.
+    url: String,
+    /// Local address to listen on, usually behind a reverse proxy.
+    listen: SocketAddr,
+}
+
+/// Register the bot with the given token to the webhook server.
+///
+/// Returns the URL of the webhook, the secret token to set it with,
+/// and the receiver of updates from it.
+pub fn register(config: &Config, token: &str) -> (String, String, UnboundedReceiver<Update>) {
+    let path = path_for_token(token);
+    let url = format!("{}{}", config.url, path);
+    let secret_token = secret_token_for_token(token);
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let route = Route {
+        secret_token: secret_token.clone(),
+        sender,
+    };
+    ROUTES.lock().insert(path, route);
+    (url, secret_token, receiver)
+}
+
+/// The path is derived from the token so that it cannot be guessed,
+/// without exposing the token itself in e.g. proxy logs.
+fn path_for_token(token: &str) -> String {
+    format!("/{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// The secret token is derived from the bot token as well, but differently from the path,
+/// so that knowing the path isn't enough to forge requests.
+fn secret_token_for_token(token: &str) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(format!("secret_token:{token}").as_bytes())
+    )
+}
+
+/// Serve webhook requests until shutdown.
+///
+/// Connections are spawned with the given spawner, and closed once their in-flight requests
+/// finish after shutdown.
+pub async fn serve(config: &'static Config, spawner: Arc<TaskSpawner>, stop_signal: Receiver<()>) {
+    let listener = match TcpListener::bind(config.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("failed to listen on {}: {:?}", config.listen, e);
+            return;
+        }
+    };
+    info!("webhook listening on {}", config.listen);
+    let stop_signal = stop_signal.shared();
+    let accept_loop = async {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("failed to accept connection: {:?}", e);
+                    continue;
+                }
+            };
+            let stop_signal = stop_signal.clone();
+            spawner.spawn(async move {
+                let service = service_fn(handle_request);
+                let connection =
+                    http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+                pin_mut!(connection);
+                let result = match future::select(stop_signal, connection).await {
+                    Either::Left((_, mut connection)) => {
+                        connection.as_mut().graceful_shutdown();
+                        connection.await
+                    }
+                    Either::Right((result, _)) => result,
+                };
+                if let Err(e) = result {
+                    debug!("webhook connection error: {:?}", e);
+                }
+            });
+        }
+    };
+    pin_mut!(accept_loop);
+    future::select(stop_signal.clone(), accept_loop).await;
+}
+
+async fn handle_request(request: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    let status = dispatch_update(request).await;
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = status;
+    Ok(response)
+}
+
+async fn dispatch_update(request: Request<Incoming>) -> StatusCode {
+    if request.method() != Method::POST {
+        return StatusCode::METHOD_NOT_ALLOWED;
+    }
+    let sender = match ROUTES.lock().get(request.uri().path()) {
+        Some(route) => {
+            let secret_token = request.headers().get(SECRET_TOKEN_HEADER);
+            if secret_token.map(|value| value.as_bytes()) != Some(route.secret_token.as_bytes()) {
+                return StatusCode::UNAUTHORIZED;
+            }
+            route.sender.clone()
+        }
+        None => return StatusCode::NOT_FOUND,
+    };
+    let body = match request.into_body().collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(e) => {
+            warn!("failed to read webhook request: {:?}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    match serde_json::from_slice::<Update>(&body) {
+        Ok(update) => {
+            // We don't care if the bot has stopped.
+            let _ = sender.send(update);
+            StatusCode::OK
+        }
+        Err(e) => {
+            warn!("failed to parse webhook update: {:?}", e);
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register() {
+        let config = Config {
+            url: "https://example.com/bot".to_string(),
+            listen: DEFAULT_LISTEN_ADDR.parse().unwrap(),
+        };
+        let (url, secret_token, _receiver) = register(&config, "123:secret");
+        assert!(url.starts_with("https://example.com/bot/"));
+        assert!(!url.contains("secret"));
+        let path = &url["https://example.com/bot".len()..];
+        assert!(ROUTES.lock().contains_key(path));
+        assert!(!url.contains(&secret_token));
+        assert_eq!(ROUTES.lock()[path].secret_token, secret_token);
+    }
+}